@@ -1,7 +1,14 @@
 fn main() {
+    println!("cargo:rerun-if-changed=src/ops");
+
+    // The inline assembly backend does not need the C library.
+    if std::env::var_os("CARGO_FEATURE_ASM_BACKEND").is_some() {
+        return;
+    }
+
     cc::Build::new()
         .file("src/ops/select.c")
         .file("src/ops/swap.c")
-        // .file("src/ops/compare.c")
+        .file("src/ops/compare.c")
         .compile("ops");
 }
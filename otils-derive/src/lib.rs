@@ -0,0 +1,122 @@
+// Implements `#[derive(ObliviousOps)]` for structs. Every field must itself
+// implement `ObliviousOps`.
+//
+// - `oselect` selects each field independently.
+// - `oequal` combines the equality of every field without short circuiting.
+// - `ocompare` compares the fields marked `#[oblivious(key)]` lexicographically,
+//   in declaration order. If no field is marked, every field is a key.
+//
+// All fields are always compared, so the work done does not depend on where the
+// first difference is.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index, Member};
+
+#[proc_macro_derive(ObliviousOps, attributes(oblivious))]
+pub fn derive_oblivious_ops(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "ObliviousOps can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut members = Vec::new();
+    let mut keys = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        };
+
+        let mut is_key = false;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("oblivious"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("key") {
+                    is_key = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `key`"))
+                }
+            })?;
+        }
+
+        if is_key {
+            keys.push((member.clone(), field.ty.clone()));
+        }
+        members.push((member, field.ty.clone()));
+    }
+
+    if keys.is_empty() {
+        keys = members.clone();
+    }
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::otils::ObliviousOps));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let select_fields = members
+        .iter()
+        .map(|(m, ty)| quote!(#m: <#ty as ::otils::ObliviousOps>::oselect(cond, a.#m, b.#m)));
+    let select_body = match fields {
+        Fields::Unit => quote!({
+            let _ = (cond, a, b);
+            Self
+        }),
+        _ => quote!(Self { #(#select_fields),* }),
+    };
+
+    let equal_fields = members
+        .iter()
+        .map(|(m, ty)| quote!(& <#ty as ::otils::ObliviousOps>::oequal(&a.#m, &b.#m)));
+
+    let compare_fields = keys.iter().map(|(m, ty)| {
+        quote! {
+            let c = <#ty as ::otils::ObliviousOps>::ocompare(&a.#m, &b.#m);
+            res = <i8 as ::otils::ObliviousOps>::oselect(
+                <i8 as ::otils::ObliviousOps>::oequal(&res, &0),
+                c,
+                res,
+            );
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::otils::ObliviousOps for #name #ty_generics #where_clause {
+            fn oselect(cond: ::otils::Condition, a: Self, b: Self) -> Self {
+                #select_body
+            }
+
+            fn oequal(a: &Self, b: &Self) -> ::otils::Condition {
+                let _ = (a, b);
+                ::otils::Condition::TRUE #(#equal_fields)*
+            }
+
+            #[allow(unused_mut)]
+            fn ocompare(a: &Self, b: &Self) -> i8 {
+                let _ = (a, b);
+                let mut res: i8 = 0;
+                #(#compare_fields)*
+                res
+            }
+        }
+    })
+}
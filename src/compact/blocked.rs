@@ -0,0 +1,195 @@
+use super::or_compact;
+use crate::ops::{self, Condition};
+use crate::{ObliviousOps, ObliviousOrd};
+use rayon::ThreadPool;
+
+// Tight compaction in blocks of BLOCK elements, so that most of the moves are
+// of whole blocks, which ops::swap does with vector instructions, rather than
+// of single elements.
+//
+// A scan compacts each block with ORCompact, rotated so that its marked
+// elements start where those of a partial block before it end, and tops that
+// block up from it. Afterwards every block is full or empty, except for the
+// last, and ORCompact over whole blocks moves the full blocks and then the
+// partial one to the front.
+//
+// This is not a linear time compaction. Each element is swapped about
+// (log BLOCK) / 2 + 1 times on its own, and then (log (n / BLOCK)) / 2 times as
+// part of a block, so there are still Θ(n log n) element moves. What it saves is
+// a constant factor: the block swaps move BLOCK elements with a few vector
+// instructions and need one condition per block rather than per element.
+//
+// The marked elements do not keep their order. With several threads, the
+// partial block that ends each run is merged into the one that ends the next,
+// past the full blocks of that run.
+const BLOCK: usize = 64;
+
+pub fn blocked_compact<T>(data: &mut [T], bits: &[Condition]) {
+    if data.len() < 2 * BLOCK {
+        or_compact::or_compact(data, bits);
+        return;
+    }
+
+    let (blocks, _) = data.as_chunks_mut::<BLOCK>();
+    let mut full = vec![Condition::FALSE; blocks.len()];
+    let fill = fill_blocks(blocks, bits, &mut full);
+    full[blocks.len() - 1] = Condition::TRUE;
+
+    merge_tail(data, bits, fill);
+    let (blocks, tail) = data.as_chunks_mut::<BLOCK>();
+    or_compact::or_compact(blocks, &full[..]);
+    place_tail(blocks, tail, &full);
+}
+
+// Each thread scans a run of blocks. The runs end in a partial block each,
+// and those are merged in turn before the blocks are compacted.
+pub fn parallel_blocked_compact<T: Send>(
+    data: &mut [T],
+    bits: &[Condition],
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    if threads <= 1 || data.len() < 2 * BLOCK * threads {
+        blocked_compact(data, bits);
+        return;
+    }
+
+    let (blocks, _) = data.as_chunks_mut::<BLOCK>();
+    let mut full = vec![Condition::FALSE; blocks.len()];
+    let run = blocks.len().div_ceil(threads);
+    let mut fills = vec![0; blocks.len().div_ceil(run)];
+    parallel_fill_blocks(blocks, bits, &mut full, &mut fills, run, pool);
+
+    let mut fill = fills[0];
+    for (r, &count) in fills.iter().enumerate().skip(1) {
+        let (prev, last) = (r * run - 1, ((r + 1) * run).min(blocks.len()) - 1);
+        let (l_blocks, r_blocks) = blocks.split_at_mut(last);
+        let marks: Vec<Condition> = (0..BLOCK).map(|i| usize::olt(&i, &count)).collect();
+        or_compact::or_off_compact(&mut r_blocks[0], &marks[..], fill % BLOCK);
+        (fill, full[prev]) = merge(&mut l_blocks[prev], &mut r_blocks[0], fill, count);
+    }
+    full[blocks.len() - 1] = Condition::TRUE;
+
+    merge_tail(data, bits, fill);
+    let (blocks, tail) = data.as_chunks_mut::<BLOCK>();
+    or_compact::parallel_or_compact(blocks, &full[..], pool, threads);
+    place_tail(blocks, tail, &full);
+}
+
+// Leaves every block but the last full or empty, and sets full for the full
+// ones. Returns the number of marked elements at the front of the last block.
+fn fill_blocks<T>(blocks: &mut [[T; BLOCK]], bits: &[Condition], full: &mut [Condition]) -> usize {
+    let mut fill = 0;
+    for j in 0..blocks.len() {
+        let block_bits = &bits[(j * BLOCK)..((j + 1) * BLOCK)];
+        let count: usize = block_bits.iter().map(|b| b.as_u8() as usize).sum();
+        let (l_blocks, r_blocks) = blocks.split_at_mut(j);
+        or_compact::or_off_compact(&mut r_blocks[0], block_bits, fill % BLOCK);
+        if j == 0 {
+            fill = count;
+        } else {
+            (fill, full[j - 1]) = merge(&mut l_blocks[j - 1], &mut r_blocks[0], fill, count);
+        }
+    }
+    fill
+}
+
+fn parallel_fill_blocks<T: Send>(
+    blocks: &mut [[T; BLOCK]],
+    bits: &[Condition],
+    full: &mut [Condition],
+    fills: &mut [usize],
+    run: usize,
+    pool: &ThreadPool,
+) {
+    if fills.len() <= 1 {
+        fills[0] = fill_blocks(blocks, bits, full);
+        return;
+    }
+
+    let mid = fills.len() / 2;
+    let (l_blocks, r_blocks) = blocks.split_at_mut(mid * run);
+    let (l_bits, r_bits) = bits.split_at(mid * run * BLOCK);
+    let (l_full, r_full) = full.split_at_mut(mid * run);
+    let (l_fills, r_fills) = fills.split_at_mut(mid);
+
+    pool.scope(|s| {
+        s.spawn(|_| parallel_fill_blocks(l_blocks, l_bits, l_full, l_fills, run, pool));
+        s.spawn(|_| parallel_fill_blocks(r_blocks, r_bits, r_full, r_fills, run, pool));
+    });
+}
+
+// Tops up buffer, which holds fill marked elements at its front, from cur, whose
+// count marked elements were compacted to start at offset fill. Those that do
+// not fit wrap around to the front of cur. Unless buffer is full, the blocks are
+// then swapped, so the partial block is always cur. Returns its fill and whether
+// buffer is full.
+fn merge<T>(
+    buffer: &mut [T; BLOCK],
+    cur: &mut [T; BLOCK],
+    fill: usize,
+    count: usize,
+) -> (usize, Condition) {
+    for i in 0..BLOCK {
+        ops::swap(!usize::olt(&i, &fill), &mut buffer[i], &mut cur[i]);
+    }
+
+    let total = fill + count;
+    let full = !usize::olt(&total, &BLOCK);
+    ops::swap(!full, buffer, cur);
+    (usize::oselect(full, total.wrapping_sub(BLOCK), total), full)
+}
+
+// Compacts the last block, which holds fill marked elements at its front,
+// together with the elements after the last block. Those that do not fit are
+// left at the front of the tail.
+fn merge_tail<T>(data: &mut [T], bits: &[Condition], fill: usize) {
+    let start = (data.len() / BLOCK - 1) * BLOCK;
+    if start + BLOCK == data.len() {
+        return;
+    }
+
+    let marks: Vec<Condition> = (0..BLOCK)
+        .map(|i| usize::olt(&i, &fill))
+        .chain(bits[(start + BLOCK)..].iter().copied())
+        .collect();
+    or_compact::or_compact(&mut data[start..], &marks[..]);
+}
+
+// Moves the front of the tail to the block after the last marked one, where
+// the elements that did not fit in the partial block go. If every block is
+// marked, that is where the tail already is.
+fn place_tail<T>(blocks: &mut [[T; BLOCK]], tail: &mut [T], full: &[Condition]) {
+    if tail.is_empty() {
+        return;
+    }
+
+    let marked: usize = full.iter().map(|b| b.as_u8() as usize).sum();
+    for (j, block) in blocks.iter_mut().enumerate() {
+        let cond = usize::oequal(&j, &marked);
+        for (x, y) in block.iter_mut().zip(tail.iter_mut()) {
+            ops::swap(cond, x, y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate test;
+    use test::Bencher;
+
+    #[bench]
+    fn bench_blocked_compact(b: &mut Bencher) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+        let size = 0x100000;
+        let mut v: Vec<usize> = (0..size).collect();
+        let bits: Vec<Condition> = v.iter().map(|x| (x % 2 != 0).into()).collect();
+
+        b.iter(|| parallel_blocked_compact(&mut v[..], &bits, &pool, 8))
+    }
+}
@@ -0,0 +1,225 @@
+use super::or_compact::{or_compact, or_off_compact, parallel_or_compact, parallel_or_off_compact};
+use crate::ops::{self, Condition};
+use crate::ObliviousOrd;
+use rayon::ThreadPool;
+
+// Compaction into a buffer of len slots, for when a public bound len on the
+// number of marked elements is known.
+//
+// The slice is cut into chunks of l = len.next_power_of_two() slots. Each chunk
+// is compacted with ORCompact so that its marked elements start, cyclically,
+// where those of the chunks before it end, and is then swapped into the first
+// chunk. At most len elements are marked, so the chunks fill disjoint slots and
+// the marked elements end up at the front, in order. This takes O(n log len)
+// work rather than O(n log n). If the length is not a multiple of l, the last
+// chunk is the last l slots, the front of which has already been emptied.
+//
+// Returns whether at most len elements were marked. The work does not depend on
+// it, and if more were marked the slice is still only permuted, but not all of
+// the marked elements need be in the first len slots.
+pub fn loose_compact<T>(data: &mut [T], bits: &[Condition], len: usize) -> Condition {
+    let n = data.len();
+    let l = len.next_power_of_two();
+    let fits = fits(bits, len);
+    if n < 2 * l {
+        or_compact(data, bits);
+        return fits;
+    }
+
+    let full = n / l * l;
+    let (offsets, counts) = chunk_counts(bits, l);
+    for ((chunk, chunk_bits), &offset) in
+        data[..full].chunks_mut(l).zip(bits.chunks(l)).zip(&offsets)
+    {
+        or_off_compact(chunk, chunk_bits, offset % l);
+    }
+    let (acc, chunks) = data[..full].split_at_mut(l);
+    fold_columns(
+        acc,
+        chunks.chunks_mut(l).collect(),
+        &offsets[1..],
+        &counts[1..],
+        0,
+    );
+
+    if full < n {
+        let tail_bits = tail_bits(bits, l);
+        let (acc, rest) = data.split_at_mut(l);
+        let tail = &mut rest[(n - 2 * l)..];
+        or_off_compact(tail, &tail_bits[..], offsets[n / l] % l);
+        fold_columns(acc, vec![tail], &offsets[(n / l)..], &counts[(n / l)..], 0);
+    }
+    fits
+}
+
+pub fn parallel_loose_compact<T: Send>(
+    data: &mut [T],
+    bits: &[Condition],
+    len: usize,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Condition {
+    let n = data.len();
+    let l = len.next_power_of_two();
+    if threads <= 1 {
+        return loose_compact(data, bits, len);
+    }
+    let fits = fits(bits, len);
+    if n < 2 * l {
+        parallel_or_compact(data, bits, pool, threads);
+        return fits;
+    }
+
+    let full = n / l * l;
+    let (offsets, counts) = chunk_counts(bits, l);
+    parallel_compact_chunks(&mut data[..full], &bits[..full], &offsets, l, pool, threads);
+    let (acc, chunks) = data[..full].split_at_mut(l);
+    let chunks = chunks.chunks_mut(l).collect();
+    parallel_fold_columns(acc, chunks, &offsets[1..], &counts[1..], 0, pool, threads);
+
+    if full < n {
+        let tail_bits = tail_bits(bits, l);
+        let (acc, rest) = data.split_at_mut(l);
+        let tail = &mut rest[(n - 2 * l)..];
+        parallel_or_off_compact(tail, &tail_bits[..], offsets[n / l] % l, pool, threads);
+        let (offsets, counts) = (&offsets[(n / l)..], &counts[(n / l)..]);
+        parallel_fold_columns(acc, vec![tail], offsets, counts, 0, pool, threads);
+    }
+    fits
+}
+
+fn fits(bits: &[Condition], len: usize) -> Condition {
+    let m: usize = bits.iter().map(|b| b.as_u8() as usize).sum();
+    !usize::olt(&len, &m)
+}
+
+// The number of marked elements in each chunk, and where they go in the first
+// chunk. The last entry is for the rest of the slice after the full chunks.
+fn chunk_counts(bits: &[Condition], l: usize) -> (Vec<usize>, Vec<usize>) {
+    let counts: Vec<usize> = bits
+        .chunks(l)
+        .map(|c| c.iter().map(|b| b.as_u8() as usize).sum())
+        .collect();
+    let offsets = counts
+        .iter()
+        .scan(0, |filled, &m| {
+            let offset = *filled;
+            *filled += m;
+            Some(offset)
+        })
+        .collect();
+    (offsets, counts)
+}
+
+// The marks of the last l slots, where the slots of full chunks are now empty.
+fn tail_bits(bits: &[Condition], l: usize) -> Vec<Condition> {
+    let n = bits.len();
+    let full = n / l * l;
+    (n - l..n)
+        .map(|i| if i < full { Condition::FALSE } else { bits[i] })
+        .collect()
+}
+
+fn parallel_compact_chunks<T: Send>(
+    data: &mut [T],
+    bits: &[Condition],
+    offsets: &[usize],
+    l: usize,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let chunks = data.len() / l;
+    if chunks == 1 {
+        parallel_or_off_compact(data, bits, offsets[0] % l, pool, threads);
+        return;
+    } else if threads <= 1 {
+        for ((chunk, chunk_bits), &offset) in data.chunks_mut(l).zip(bits.chunks(l)).zip(offsets) {
+            or_off_compact(chunk, chunk_bits, offset % l);
+        }
+        return;
+    }
+
+    let mid = chunks / 2;
+    let (l_data, r_data) = data.split_at_mut(mid * l);
+    let (l_bits, r_bits) = bits.split_at(mid * l);
+    let (l_offsets, r_offsets) = offsets.split_at(mid);
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+
+    pool.scope(|s| {
+        s.spawn(|_| parallel_compact_chunks(l_data, l_bits, l_offsets, l, pool, l_threads));
+        s.spawn(|_| parallel_compact_chunks(r_data, r_bits, r_offsets, l, pool, r_threads));
+    });
+}
+
+fn parallel_fold_columns<T: Send>(
+    acc: &mut [T],
+    chunks: Vec<&mut [T]>,
+    offsets: &[usize],
+    counts: &[usize],
+    start: usize,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let width = acc.len();
+    if threads <= 1 || width < 2 {
+        fold_columns(acc, chunks, offsets, counts, start);
+        return;
+    }
+
+    let (l_acc, r_acc) = acc.split_at_mut(width / 2);
+    let (l_chunks, r_chunks): (Vec<_>, Vec<_>) = chunks
+        .into_iter()
+        .map(|chunk| chunk.split_at_mut(width / 2))
+        .unzip();
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+
+    pool.scope(|s| {
+        s.spawn(|_| {
+            parallel_fold_columns(l_acc, l_chunks, offsets, counts, start, pool, l_threads)
+        });
+        s.spawn(|_| {
+            let r_start = start + width / 2;
+            parallel_fold_columns(r_acc, r_chunks, offsets, counts, r_start, pool, r_threads)
+        });
+    });
+}
+
+// Swaps the marked elements of each compacted chunk into the first chunk.
+// Column i of the slices is slot start + i of a chunk.
+fn fold_columns<T>(
+    acc: &mut [T],
+    mut chunks: Vec<&mut [T]>,
+    offsets: &[usize],
+    counts: &[usize],
+    start: usize,
+) {
+    for ((chunk, &offset), &m) in chunks.iter_mut().zip(offsets).zip(counts) {
+        for i in 0..acc.len() {
+            let b = usize::olt(&(start + i).wrapping_sub(offset), &m);
+            ops::swap(b, &mut acc[i], &mut chunk[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate test;
+    use test::Bencher;
+
+    #[bench]
+    fn bench_loose_compact(b: &mut Bencher) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+        let size = 0x100000;
+        let mut v: Vec<usize> = (0..size).collect();
+        let bits: Vec<Condition> = v.iter().map(|x| (x % 64 == 0).into()).collect();
+
+        b.iter(|| parallel_loose_compact(&mut v[..], &bits, size / 32, &pool, 8))
+    }
+}
@@ -0,0 +1,143 @@
+use crate::ops::Condition;
+
+// Marks packed 64 to a word, for compacting slices too large for one Condition
+// per element. Counting the marks in a range masks the words at either end and
+// popcounts the words it covers, so the time only depends on the range.
+// count_ones compiles to popcnt, or to a fixed sequence of shifts and adds on
+// targets without it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitMarks {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitMarks {
+    // len unmarked elements.
+    pub fn new(len: usize) -> Self {
+        BitMarks {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, i: usize) -> Condition {
+        assert!(i < self.len);
+        Condition::from(((self.words[i / 64] >> (i % 64)) & 1) as u8)
+    }
+
+    pub fn set(&mut self, i: usize, mark: Condition) {
+        assert!(i < self.len);
+        let word = &mut self.words[i / 64];
+        *word = (*word & !(1 << (i % 64))) | ((mark.as_u8() as u64) << (i % 64));
+    }
+
+    // The number of marked elements.
+    pub fn count(&self) -> usize {
+        self.as_range().count()
+    }
+
+    pub(crate) fn as_range(&self) -> BitRange<'_> {
+        BitRange {
+            words: &self.words,
+            start: 0,
+            len: self.len,
+        }
+    }
+}
+
+impl FromIterator<Condition> for BitMarks {
+    fn from_iter<I: IntoIterator<Item = Condition>>(iter: I) -> Self {
+        let mut words = Vec::new();
+        let mut len = 0;
+        for mark in iter {
+            if len % 64 == 0 {
+                words.push(0);
+            }
+            words[len / 64] |= (mark.as_u8() as u64) << (len % 64);
+            len += 1;
+        }
+        BitMarks { words, len }
+    }
+}
+
+impl From<&[Condition]> for BitMarks {
+    fn from(bits: &[Condition]) -> Self {
+        bits.iter().copied().collect()
+    }
+}
+
+// A view of the marks of a subslice, which the compaction recursion splits
+// alongside the data.
+pub trait Marks: Copy + Sync {
+    fn get(self, i: usize) -> Condition;
+    fn count(self) -> usize;
+    fn split_at(self, mid: usize) -> (Self, Self);
+}
+
+impl Marks for &[Condition] {
+    fn get(self, i: usize) -> Condition {
+        self[i]
+    }
+
+    fn count(self) -> usize {
+        self.iter().map(|b| b.as_u8() as usize).sum()
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        <[Condition]>::split_at(self, mid)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct BitRange<'a> {
+    words: &'a [u64],
+    start: usize,
+    len: usize,
+}
+
+impl Marks for BitRange<'_> {
+    fn get(self, i: usize) -> Condition {
+        assert!(i < self.len);
+        let i = self.start + i;
+        Condition::from(((self.words[i / 64] >> (i % 64)) & 1) as u8)
+    }
+
+    fn count(self) -> usize {
+        if self.len == 0 {
+            return 0;
+        }
+
+        let end = self.start + self.len - 1;
+        let mut total = 0;
+        for w in (self.start / 64)..=(end / 64) {
+            let mut word = self.words[w];
+            if w == self.start / 64 {
+                word &= u64::MAX << (self.start % 64);
+            }
+            if w == end / 64 {
+                word &= u64::MAX >> (63 - end % 64);
+            }
+            total += word.count_ones() as usize;
+        }
+        total
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        assert!(mid <= self.len);
+        let l_range = BitRange { len: mid, ..self };
+        let r_range = BitRange {
+            start: self.start + mid,
+            len: self.len - mid,
+            ..self
+        };
+        (l_range, r_range)
+    }
+}
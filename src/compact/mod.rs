@@ -1,17 +1,174 @@
+mod blocked;
+mod loose;
+mod marks;
 mod or_compact;
+use crate::Condition;
 use rayon::ThreadPool;
 
-pub fn compact<T>(data: &mut [T], bits: &[bool]) {
-    or_compact::or_compact(data, bits);
+pub use marks::BitMarks;
+
+// The tight compaction used by the `_with` functions. ORCompact is the default.
+// Blocked compaction moves most elements as whole blocks. It also takes
+// O(n log n) work, but with a smaller constant for small elements, and it does
+// not keep the marked elements in order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompactAlgorithm {
+    #[default]
+    OrCompact,
+    Blocked,
+}
+
+impl CompactAlgorithm {
+    fn compact<T>(self, data: &mut [T], bits: &[Condition]) {
+        match self {
+            CompactAlgorithm::OrCompact => or_compact::or_compact(data, bits),
+            CompactAlgorithm::Blocked => blocked::blocked_compact(data, bits),
+        }
+    }
+
+    fn par_compact<T: Send>(
+        self,
+        data: &mut [T],
+        bits: &[Condition],
+        pool: &ThreadPool,
+        threads: usize,
+    ) {
+        match self {
+            CompactAlgorithm::OrCompact => {
+                or_compact::parallel_or_compact(data, bits, pool, threads)
+            }
+            CompactAlgorithm::Blocked => {
+                blocked::parallel_blocked_compact(data, bits, pool, threads)
+            }
+        }
+    }
+}
+
+pub fn compact<T>(data: &mut [T], bits: &[Condition]) {
+    compact_with(data, bits, CompactAlgorithm::default());
+}
+
+pub fn par_compact<T: Send>(data: &mut [T], bits: &[Condition], pool: &ThreadPool, threads: usize) {
+    par_compact_with(data, bits, CompactAlgorithm::default(), pool, threads);
+}
+
+pub fn compact_with<T>(data: &mut [T], bits: &[Condition], algorithm: CompactAlgorithm) {
+    algorithm.compact(data, bits);
+}
+
+pub fn par_compact_with<T: Send>(
+    data: &mut [T],
+    bits: &[Condition],
+    algorithm: CompactAlgorithm,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    algorithm.par_compact(data, bits, pool, threads);
+}
+
+// Moves the marked elements into the first len slots, given that at most len
+// are marked. Takes O(n log len) work. Returns whether at most len were marked,
+// as a Condition, so the bound is not revealed unless the caller checks it.
+pub fn loose_compact<T>(data: &mut [T], bits: &[Condition], len: usize) -> Condition {
+    loose::loose_compact(data, bits, len)
+}
+
+pub fn par_loose_compact<T: Send>(
+    data: &mut [T],
+    bits: &[Condition],
+    len: usize,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Condition {
+    loose::parallel_loose_compact(data, bits, len, pool, threads)
+}
+
+// compact and par_compact with packed marks, one bit rather than one byte per
+// element.
+pub fn compact_marks<T>(data: &mut [T], marks: &BitMarks) {
+    or_compact::or_compact(data, marks.as_range());
+}
+
+pub fn par_compact_marks<T: Send>(
+    data: &mut [T],
+    marks: &BitMarks,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    or_compact::parallel_or_compact(data, marks.as_range(), pool, threads);
+}
+
+// The inverse of compact: moves the first m elements, where m is the number of
+// marks, to the marked positions, keeping their order. The other elements fill
+// the unmarked positions.
+pub fn distribute<T>(data: &mut [T], bits: &[Condition]) {
+    or_compact::or_distribute(data, bits);
+}
+
+pub fn par_distribute<T: Send>(
+    data: &mut [T],
+    bits: &[Condition],
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    or_compact::parallel_or_distribute(data, bits, pool, threads);
 }
 
-pub fn par_compact<T: Send>(data: &mut [T], bits: &[bool], pool: &ThreadPool, threads: usize) {
-    or_compact::parallel_or_compact(data, bits, pool, threads);
+// Moves the elements for which keep holds to the front, in order, and returns
+// how many there are. The marks are computed here, and only the count is
+// revealed.
+pub fn compact_by<T, F: Fn(&T) -> Condition>(data: &mut [T], keep: F) -> usize {
+    let bits: Vec<Condition> = data.iter().map(keep).collect();
+    compact(data, &bits);
+    count(&bits)
+}
+
+pub fn par_compact_by<T: Send, F: Fn(&T) -> Condition + Sync>(
+    data: &mut [T],
+    keep: F,
+    pool: &ThreadPool,
+    threads: usize,
+) -> usize {
+    let mut bits = vec![Condition::FALSE; data.len()];
+    parallel_marks(data, &mut bits, &keep, pool, threads);
+    par_compact(data, &bits, pool, threads);
+    count(&bits)
+}
+
+fn parallel_marks<T: Send, F: Fn(&T) -> Condition + Sync>(
+    data: &mut [T],
+    bits: &mut [Condition],
+    keep: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    if threads <= 1 || data.len() < 2 {
+        for (b, x) in bits.iter_mut().zip(data.iter()) {
+            *b = keep(x);
+        }
+        return;
+    }
+
+    let mid = data.len() / 2;
+    let (l_data, r_data) = data.split_at_mut(mid);
+    let (l_bits, r_bits) = bits.split_at_mut(mid);
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+
+    pool.scope(|s| {
+        s.spawn(|_| parallel_marks(l_data, l_bits, keep, pool, l_threads));
+        s.spawn(|_| parallel_marks(r_data, r_bits, keep, pool, r_threads));
+    });
+}
+
+fn count(bits: &[Condition]) -> usize {
+    bits.iter().map(|b| b.as_u8() as usize).sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ObliviousOps;
 
     #[test]
     fn test_compact() {
@@ -24,7 +181,7 @@ mod tests {
                 let mut data: Vec<$t> = $v.into_iter().collect();
                 let real: Vec<$t> = $v.into_iter().filter(|x| $f(x)).collect();
 
-                let bits: Vec<bool> = data.iter().map(|x| $f(x)).collect();
+                let bits: Vec<Condition> = data.iter().map(|x| $f(x).into()).collect();
                 par_compact(&mut data[..], &bits[..], &pool, 2);
                 assert_eq!(&data[0..real.len()], &real[..]);
             };
@@ -33,5 +190,198 @@ mod tests {
         test_compact!((1..101), |x| x % 2 == 0, i64);
         test_compact!((1..101), |x| x % 3 == 0, i64);
         test_compact!((1..101), |x| x % 7 == 0, i64);
+        test_compact!((1..101), |x| x % 2 == 0, u32);
+        test_compact!((1..101), |x| x % 3 == 0, u8);
+    }
+
+    #[test]
+    fn test_compact_algorithms() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+
+        // Blocked compaction does not keep the order, so only the sets of
+        // marked elements are compared.
+        let sorted = |v: &[u64]| {
+            let mut v = v.to_vec();
+            v.sort();
+            v
+        };
+
+        for n in [0, 1, 2, 3, 7, 64, 100, 129, 300, 512, 1000, 0x5003] {
+            for p in [1, 3, 10] {
+                let data: Vec<u64> = (0..n).map(|i| i * 13 % 101).collect();
+                let bits: Vec<Condition> = data.iter().map(|x| (x % p == 0).into()).collect();
+                let real: Vec<u64> = data.iter().copied().filter(|x| x % p == 0).collect();
+
+                let mut v = data.clone();
+                compact_with(&mut v, &bits, CompactAlgorithm::Blocked);
+                assert_eq!(sorted(&v[..real.len()]), sorted(&real));
+
+                for threads in [2, 4] {
+                    let mut v = data.clone();
+                    par_compact_with(&mut v, &bits, CompactAlgorithm::Blocked, &pool, threads);
+                    assert_eq!(sorted(&v[..real.len()]), sorted(&real));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_loose_compact() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        for n in [0, 5, 16, 100, 1000] {
+            for p in [7, 50, 1000] {
+                let data: Vec<u64> = (0..n).map(|i| i * 37 % 1009).collect();
+                let bits: Vec<Condition> = data.iter().map(|x| (x % p == 0).into()).collect();
+                let real: Vec<u64> = data.iter().copied().filter(|x| x % p == 0).collect();
+
+                for len in [real.len(), 2 * real.len() + 1, 3 * real.len()] {
+                    let mut v = data.clone();
+                    assert!(bool::from(loose_compact(&mut v, &bits, len)));
+                    assert_eq!(&v[..real.len()], &real[..]);
+
+                    for threads in [2, 3] {
+                        let mut v = data.clone();
+                        let fits = par_loose_compact(&mut v, &bits, len, &pool, threads);
+                        assert!(bool::from(fits));
+                        assert_eq!(&v[..real.len()], &real[..]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_loose_compact_bound() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+
+        // 10 marked elements do not fit in 9 slots, but the slice is still
+        // only permuted.
+        for threads in [1, 2] {
+            let mut data: Vec<u64> = (0..100).collect();
+            let bits: Vec<Condition> = data.iter().map(|x| (x % 10 == 0).into()).collect();
+            let fits = par_loose_compact(&mut data, &bits, 9, &pool, threads);
+            assert!(!bool::from(fits));
+
+            data.sort();
+            assert_eq!(data, (0..100).collect::<Vec<u64>>());
+        }
+    }
+
+    #[test]
+    fn test_bit_marks() {
+        let bits: Vec<Condition> = (0..200).map(|i| (i % 3 == 0 || i > 150).into()).collect();
+        let mut marks = BitMarks::from(&bits[..]);
+        assert_eq!(marks.len(), 200);
+        assert_eq!(marks.count(), 67 + 33);
+        for (i, &b) in bits.iter().enumerate() {
+            assert_eq!(bool::from(marks.get(i)), bool::from(b));
+        }
+
+        marks.set(0, Condition::FALSE);
+        marks.set(1, Condition::TRUE);
+        marks.set(199, Condition::TRUE);
+        assert!(!bool::from(marks.get(0)));
+        assert!(bool::from(marks.get(1)));
+        assert!(bool::from(marks.get(199)));
+        assert_eq!(marks.count(), 100);
+        assert_eq!(BitMarks::new(70).count(), 0);
+    }
+
+    #[test]
+    fn test_compact_marks() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        for n in [0, 1, 63, 64, 65, 300, 1000] {
+            let data: Vec<u64> = (0..n).map(|i| i * 7 % 13).collect();
+            let bits: Vec<Condition> = data.iter().map(|x| (x % 3 == 0).into()).collect();
+            let marks: BitMarks = bits.iter().copied().collect();
+
+            let mut expected = data.clone();
+            compact(&mut expected, &bits);
+
+            let mut v = data.clone();
+            compact_marks(&mut v, &marks);
+            assert_eq!(v, expected);
+
+            let mut v = data.clone();
+            par_compact_marks(&mut v, &marks, &pool, 3);
+            assert_eq!(v, expected);
+        }
+    }
+
+    #[test]
+    fn test_distribute() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        for n in 0..70u64 {
+            let bits: Vec<Condition> = (0..n).map(|i| ((i * 37) % 7 < 3).into()).collect();
+            let marked: Vec<usize> = (0..n as usize).filter(|&i| bool::from(bits[i])).collect();
+
+            let mut data: Vec<u64> = (0..n).collect();
+            distribute(&mut data, &bits);
+            for (j, &i) in marked.iter().enumerate() {
+                assert_eq!(data[i], j as u64);
+            }
+
+            for threads in [2, 3] {
+                let mut par_data: Vec<u64> = (0..n).collect();
+                par_distribute(&mut par_data, &bits, &pool, threads);
+                assert_eq!(par_data, data);
+            }
+
+            // Both directions undo each other.
+            let original: Vec<u64> = (0..n).map(|i| i * 1000).collect();
+            let mut data = original.clone();
+            compact(&mut data, &bits);
+            distribute(&mut data, &bits);
+            assert_eq!(data, original);
+
+            par_distribute(&mut data, &bits, &pool, 3);
+            par_compact(&mut data, &bits, &pool, 3);
+            assert_eq!(data, original);
+        }
+    }
+
+    #[test]
+    fn test_compact_by() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+
+        for n in [0, 1, 2, 10, 101, 1000] {
+            let real: Vec<u32> = (0..n).filter(|x| x % 3 == 0).collect();
+
+            let mut data: Vec<u32> = (0..n).collect();
+            let kept = compact_by(&mut data, |x| u32::oequal(&(x % 3), &0));
+            assert_eq!(kept, real.len());
+            assert_eq!(&data[..kept], &real[..]);
+
+            let mut data: Vec<u32> = (0..n).collect();
+            let kept = par_compact_by(&mut data, |x| u32::oequal(&(x % 3), &0), &pool, 2);
+            assert_eq!(kept, real.len());
+            assert_eq!(&data[..kept], &real[..]);
+
+            let mut data: Vec<u32> = (0..n).collect();
+            let kept = par_compact_by(&mut data, |x| u32::oequal(&(x % 3), &0), &pool, 5);
+            assert_eq!(kept, real.len());
+            assert_eq!(&data[..kept], &real[..]);
+        }
     }
 }
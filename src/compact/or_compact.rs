@@ -1,9 +1,11 @@
+use super::marks::Marks;
 use crate::ops;
+use crate::{ObliviousOps, ObliviousOrd};
 use rayon::ThreadPool;
 
-pub fn parallel_or_compact<T: Send>(
+pub fn parallel_or_compact<T: Send, M: Marks>(
     data: &mut [T],
-    bits: &[bool],
+    bits: M,
     pool: &ThreadPool,
     threads: usize,
 ) {
@@ -16,22 +18,20 @@ pub fn parallel_or_compact<T: Send>(
 
     let n1 = 1 << usize::ilog2(data.len());
     let n2 = n - n1;
-    let m = bits[0..n2].iter().map(|&b| b as usize).sum();
 
     let (l_data, r_data) = data.split_at_mut(n2);
     let (l_bits, r_bits) = bits.split_at(n2);
+    let m = l_bits.count();
     pool.scope(|s| {
         or_compact(l_data, l_bits);
         s.spawn(|_| parallel_or_off_compact(r_data, r_bits, (n1 - n2 + m) % n1, pool, threads));
     });
-    for i in 0..n2 {
-        ops::swap(i >= m, &mut l_data[i], &mut r_data[n1 - n2 + i]);
-    }
+    front_swaps(l_data, r_data, m);
 }
 
-fn parallel_or_off_compact<T: Send>(
+pub fn parallel_or_off_compact<T: Send, M: Marks>(
     data: &mut [T],
-    bits: &[bool],
+    bits: M,
     offset: usize,
     pool: &ThreadPool,
     threads: usize,
@@ -43,9 +43,9 @@ fn parallel_or_off_compact<T: Send>(
         return;
     }
 
-    let m: usize = bits[0..(n / 2)].iter().map(|&b| b as usize).sum();
     let (l_data, r_data) = data.split_at_mut(n / 2);
     let (l_bits, r_bits) = bits.split_at(n / 2);
+    let m = l_bits.count();
     let l_threads = threads / 2;
     let r_threads = threads - l_threads;
 
@@ -56,15 +56,10 @@ fn parallel_or_off_compact<T: Send>(
         });
     });
 
-    let mut s = (offset % (n / 2)) + m >= n / 2;
-    s ^= offset >= n / 2;
-    for i in 0..(n / 2) {
-        let b = s ^ (i >= (offset + m) % (n / 2));
-        ops::swap(b, &mut l_data[i], &mut r_data[i]);
-    }
+    off_swaps(l_data, r_data, offset, m);
 }
 
-pub fn or_compact<T>(data: &mut [T], bits: &[bool]) {
+pub fn or_compact<T, M: Marks>(data: &mut [T], bits: M) {
     let n = data.len();
 
     if n == 0 {
@@ -73,46 +68,169 @@ pub fn or_compact<T>(data: &mut [T], bits: &[bool]) {
 
     let n1: usize = 1 << usize::ilog2(data.len());
     let n2 = n - n1;
-    let m: usize = bits[0..n2].iter().map(|&b| b as usize).sum();
 
     let (l_data, r_data) = data.split_at_mut(n2);
     let (l_bits, r_bits) = bits.split_at(n2);
+    let m = l_bits.count();
     or_compact(l_data, l_bits);
     or_off_compact(r_data, r_bits, (n1 - n2 + m) % n1);
-    for i in 0..n2 {
-        ops::swap(i >= m, &mut l_data[i], &mut r_data[n1 - n2 + i]);
-    }
+    front_swaps(l_data, r_data, m);
 }
 
-fn or_off_compact<T>(data: &mut [T], bits: &[bool], offset: usize) {
+pub fn or_off_compact<T, M: Marks>(data: &mut [T], bits: M, offset: usize) {
     let n = data.len();
 
     if n < 2 {
         return;
     } else if n == 2 {
-        let (l_data, r_data) = data.split_at_mut(1);
-        let b = (!bits[0] & bits[1]) ^ (offset != 0);
-        ops::swap(b, &mut l_data[0], &mut r_data[0]);
+        base_swap(data, bits, offset);
         return;
     }
 
-    let m: usize = bits[0..(n / 2)].iter().map(|&b| b as usize).sum();
     let (l_data, r_data) = data.split_at_mut(n / 2);
     let (l_bits, r_bits) = bits.split_at(n / 2);
+    let m = l_bits.count();
     or_off_compact(l_data, l_bits, offset % (n / 2));
     or_off_compact(r_data, r_bits, (offset + m) % (n / 2));
 
-    let mut s = (offset % (n / 2)) + m >= n / 2;
-    s ^= offset >= n / 2;
-    for i in 0..(n / 2) {
-        let b = s ^ (i >= (offset + m) % (n / 2));
+    off_swaps(l_data, r_data, offset, m);
+}
+
+// Distribution, the inverse of compaction: the first m elements, where m is the
+// number of marks, are moved to the marked positions in order. It runs the
+// swaps of the compaction network in reverse, merges before halves.
+pub fn parallel_or_distribute<T: Send, M: Marks>(
+    data: &mut [T],
+    bits: M,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let n = data.len();
+
+    if threads <= 1 || n == 0 {
+        or_distribute(data, bits);
+        return;
+    }
+
+    let n1 = 1 << usize::ilog2(data.len());
+    let n2 = n - n1;
+
+    let (l_data, r_data) = data.split_at_mut(n2);
+    let (l_bits, r_bits) = bits.split_at(n2);
+    let m = l_bits.count();
+    front_swaps(l_data, r_data, m);
+    pool.scope(|s| {
+        or_distribute(l_data, l_bits);
+        s.spawn(|_| parallel_or_off_distribute(r_data, r_bits, (n1 - n2 + m) % n1, pool, threads));
+    });
+}
+
+fn parallel_or_off_distribute<T: Send, M: Marks>(
+    data: &mut [T],
+    bits: M,
+    offset: usize,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let n = data.len();
+
+    if threads <= 1 || n <= 2 {
+        or_off_distribute(data, bits, offset);
+        return;
+    }
+
+    let (l_data, r_data) = data.split_at_mut(n / 2);
+    let (l_bits, r_bits) = bits.split_at(n / 2);
+    let m = l_bits.count();
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+
+    off_swaps(l_data, r_data, offset, m);
+    pool.scope(|s| {
+        s.spawn(|_| parallel_or_off_distribute(l_data, l_bits, offset % (n / 2), pool, l_threads));
+        s.spawn(|_| {
+            parallel_or_off_distribute(r_data, r_bits, (offset + m) % (n / 2), pool, r_threads)
+        });
+    });
+}
+
+pub fn or_distribute<T, M: Marks>(data: &mut [T], bits: M) {
+    let n = data.len();
+
+    if n == 0 {
+        return;
+    }
+
+    let n1: usize = 1 << usize::ilog2(data.len());
+    let n2 = n - n1;
+
+    let (l_data, r_data) = data.split_at_mut(n2);
+    let (l_bits, r_bits) = bits.split_at(n2);
+    let m = l_bits.count();
+    front_swaps(l_data, r_data, m);
+    or_distribute(l_data, l_bits);
+    or_off_distribute(r_data, r_bits, (n1 - n2 + m) % n1);
+}
+
+fn or_off_distribute<T, M: Marks>(data: &mut [T], bits: M, offset: usize) {
+    let n = data.len();
+
+    if n < 2 {
+        return;
+    } else if n == 2 {
+        base_swap(data, bits, offset);
+        return;
+    }
+
+    let (l_data, r_data) = data.split_at_mut(n / 2);
+    let (l_bits, r_bits) = bits.split_at(n / 2);
+    let m = l_bits.count();
+    off_swaps(l_data, r_data, offset, m);
+    or_off_distribute(l_data, l_bits, offset % (n / 2));
+    or_off_distribute(r_data, r_bits, (offset + m) % (n / 2));
+}
+
+// The swaps that combine the two halves. They only depend on the marks, and
+// each is its own inverse, so running them in the opposite order undoes a
+// compaction.
+
+// Moves the m marked elements at the front of l_data past the elements of
+// r_data that were compacted to its end.
+fn front_swaps<T>(l_data: &mut [T], r_data: &mut [T], m: usize) {
+    let n1 = r_data.len();
+    let n2 = l_data.len();
+    for i in 0..n2 {
+        ops::swap(
+            !usize::olt(&i, &m),
+            &mut l_data[i],
+            &mut r_data[n1 - n2 + i],
+        );
+    }
+}
+
+// Merges two halves compacted cyclically, with m marked elements on the left.
+fn off_swaps<T>(l_data: &mut [T], r_data: &mut [T], offset: usize, m: usize) {
+    let half = l_data.len();
+    let mut s = !usize::olt(&((offset % half) + m), &half);
+    s ^= !usize::olt(&offset, &half);
+    let r_offset = (offset + m) % half;
+    for i in 0..half {
+        let b = s ^ !usize::olt(&i, &r_offset);
         ops::swap(b, &mut l_data[i], &mut r_data[i]);
     }
 }
 
+fn base_swap<T, M: Marks>(data: &mut [T], bits: M, offset: usize) {
+    let (l_data, r_data) = data.split_at_mut(1);
+    let b = (!bits.get(0) & bits.get(1)) ^ !usize::oequal(&offset, &0);
+    ops::swap(b, &mut l_data[0], &mut r_data[0]);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compact::BitMarks;
+    use crate::Condition;
 
     extern crate test;
     use test::Bencher;
@@ -125,9 +243,22 @@ mod tests {
             .unwrap();
         let size = 0x100000;
         let mut v: Vec<usize> = (0..size).collect();
-        let bits: Vec<bool> = v.iter().map(|x| x % 2 != 0).collect();
+        let bits: Vec<Condition> = v.iter().map(|x| (x % 2 != 0).into()).collect();
+
+        b.iter(|| parallel_or_compact(&mut v[..], &bits[..], &pool, 8))
+    }
+
+    #[bench]
+    fn bench_or_compact_marks(b: &mut Bencher) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+        let size = 0x100000;
+        let mut v: Vec<usize> = (0..size).collect();
+        let marks: BitMarks = v.iter().map(|x| (x % 2 != 0).into()).collect();
 
-        b.iter(|| parallel_or_compact(&mut v[..], &bits, &pool, 8))
+        b.iter(|| parallel_or_compact(&mut v[..], marks.as_range(), &pool, 8))
     }
 
     struct BigElem {
@@ -151,9 +282,9 @@ mod tests {
             .build()
             .unwrap();
         let size = 0x100000;
-        let mut v: Vec<BigElem> = (0..size).rev().map(|i| BigElem::new(i)).collect();
-        let mut bits: Vec<bool> = v.iter().map(|x| x.key % 2 != 0).collect();
+        let mut v: Vec<BigElem> = (0..size).rev().map(BigElem::new).collect();
+        let bits: Vec<Condition> = v.iter().map(|x| (x.key % 2 != 0).into()).collect();
 
-        b.iter(|| parallel_or_compact(&mut v[..], &mut bits, &pool, 8))
+        b.iter(|| parallel_or_compact(&mut v[..], &bits[..], &pool, 8))
     }
 }
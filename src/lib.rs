@@ -1,20 +1,37 @@
-#![feature(test)]
+#![cfg_attr(test, feature(test))]
 // #![feature(stdarch_x86_avx512)]
 
+// Lets `otils-derive` output, which names `::otils`, be used inside this crate.
+extern crate self as otils;
+
 mod compact;
-pub use crate::compact::{compact, par_compact};
+pub use crate::compact::{
+    compact, compact_by, compact_marks, compact_with, distribute, loose_compact, par_compact,
+    par_compact_by, par_compact_marks, par_compact_with, par_distribute, par_loose_compact,
+    BitMarks, CompactAlgorithm,
+};
 
 mod contains;
 pub use crate::contains::contains;
 
 mod ops;
-pub use crate::ops::{swap, ObliviousOps};
+#[cfg(feature = "subtle")]
+pub use crate::ops::Subtle;
+pub use crate::ops::{swap, Condition, ObliviousOps, ObliviousOrd};
+#[cfg(feature = "derive")]
+pub use otils_derive::ObliviousOps;
 
 mod shuffle;
 pub use crate::shuffle::{par_shuffle, shuffle};
 
 mod sort;
-pub use crate::sort::{par_sort, sort};
+pub use crate::sort::{
+    bucket_sort, par_bucket_sort, par_sort, par_sort_by, par_sort_by_key, par_sort_slice,
+    par_sort_slice_with, par_stable_sort, par_trusted_sort, sort, sort_by, sort_by_key, sort_slice,
+    sort_slice_with, stable_sort, trusted_sort, SortAlgorithm, SortOrder,
+};
+#[cfg(feature = "external")]
+pub use crate::sort::{BackingStore, ExternalVec, FileStore, MemoryStore, Record};
 
 mod max;
 pub use crate::max::Max;
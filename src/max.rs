@@ -20,3 +20,5 @@ impl_max!(i64);
 impl_max!(u64);
 impl_max!(isize);
 impl_max!(usize);
+impl_max!(i128);
+impl_max!(u128);
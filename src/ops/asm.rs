@@ -0,0 +1,189 @@
+// Inline assembly versions of the primitives in select.c, swap.c and compare.c,
+// used instead of the C library when the `asm-backend` feature is enabled.
+// Selection and swapping use cmov and comparison uses setcc, neither of which
+// branch on their inputs. The signatures match the C functions so the rest of
+// the crate does not care which backend it is built with.
+//
+// 8 and 16-bit values are widened to 32 bits since cmov has no byte form.
+use std::arch::asm;
+
+#[inline]
+unsafe fn select_32_bits(cond: u8, a: u32, b: u32) -> u32 {
+    let mut res = b;
+    asm!(
+        "test {cond}, {cond}",
+        "cmovnz {res:e}, {a:e}",
+        cond = in(reg_byte) cond,
+        a = in(reg) a,
+        res = inout(reg) res,
+        options(pure, nomem, nostack),
+    );
+    res
+}
+
+#[inline]
+pub unsafe fn select_8(cond: u8, a: i8, b: i8) -> i8 {
+    select_32_bits(cond, a as u8 as u32, b as u8 as u32) as i8
+}
+
+#[inline]
+pub unsafe fn select_16(cond: u8, a: i16, b: i16) -> i16 {
+    select_32_bits(cond, a as u16 as u32, b as u16 as u32) as i16
+}
+
+#[inline]
+pub unsafe fn select_32(cond: u8, a: i32, b: i32) -> i32 {
+    select_32_bits(cond, a as u32, b as u32) as i32
+}
+
+#[inline]
+pub unsafe fn select_64(cond: u8, a: i64, b: i64) -> i64 {
+    let mut res = b;
+    asm!(
+        "test {cond}, {cond}",
+        "cmovnz {res}, {a}",
+        cond = in(reg_byte) cond,
+        a = in(reg) a,
+        res = inout(reg) res,
+        options(pure, nomem, nostack),
+    );
+    res
+}
+
+// The swaps load and store through the pointers inside the assembly, so that
+// padding bytes, which may be uninitialized, are never read as integers in Rust.
+// Bytes and words are loaded zero extended into 32-bit registers, and only
+// their low part is stored back.
+macro_rules! impl_swap {
+    ($name: ident, $t: ty, $load: literal, $store_a: literal, $store_b: literal) => {
+        #[inline]
+        pub unsafe fn $name(cond: u8, a: *mut $t, b: *mut $t) {
+            asm!(
+                $load,
+                "mov {t}, {x}",
+                "test {cond}, {cond}",
+                "cmovnz {x}, {y}",
+                "cmovnz {y}, {t}",
+                $store_a,
+                $store_b,
+                cond = in(reg_byte) cond,
+                a = in(reg) a,
+                b = in(reg) b,
+                x = out(reg) _,
+                y = out(reg) _,
+                t = out(reg) _,
+                options(nostack),
+            );
+        }
+    };
+}
+
+impl_swap!(
+    swap8,
+    u8,
+    "movzx {x:e}, byte ptr [{a}]\nmovzx {y:e}, byte ptr [{b}]",
+    "mov byte ptr [{a}], {x:l}",
+    "mov byte ptr [{b}], {y:l}"
+);
+impl_swap!(
+    swap16,
+    u16,
+    "movzx {x:e}, word ptr [{a}]\nmovzx {y:e}, word ptr [{b}]",
+    "mov word ptr [{a}], {x:x}",
+    "mov word ptr [{b}], {y:x}"
+);
+impl_swap!(
+    swap32,
+    u32,
+    "mov {x:e}, dword ptr [{a}]\nmov {y:e}, dword ptr [{b}]",
+    "mov dword ptr [{a}], {x:e}",
+    "mov dword ptr [{b}], {y:e}"
+);
+impl_swap!(
+    swap64,
+    u64,
+    "mov {x}, qword ptr [{a}]\nmov {y}, qword ptr [{b}]",
+    "mov qword ptr [{a}], {x}",
+    "mov qword ptr [{b}], {y}"
+);
+
+#[inline]
+unsafe fn equal_64_bits(a: u64, b: u64) -> bool {
+    let res: u8;
+    asm!(
+        "cmp {a}, {b}",
+        "sete {res}",
+        a = in(reg) a,
+        b = in(reg) b,
+        res = out(reg_byte) res,
+        options(pure, nomem, nostack),
+    );
+    res != 0
+}
+
+#[inline]
+pub unsafe fn equal_8(a: i8, b: i8) -> bool {
+    equal_64_bits(a as u64, b as u64)
+}
+
+#[inline]
+pub unsafe fn equal_16(a: i16, b: i16) -> bool {
+    equal_64_bits(a as u64, b as u64)
+}
+
+#[inline]
+pub unsafe fn equal_32(a: i32, b: i32) -> bool {
+    equal_64_bits(a as u64, b as u64)
+}
+
+#[inline]
+pub unsafe fn equal_64(a: i64, b: i64) -> bool {
+    equal_64_bits(a as u64, b as u64)
+}
+
+#[inline]
+pub unsafe fn compare_8(a: i8, b: i8) -> i8 {
+    compare_64(a as i64, b as i64)
+}
+
+#[inline]
+pub unsafe fn compare_16(a: i16, b: i16) -> i8 {
+    compare_64(a as i64, b as i64)
+}
+
+#[inline]
+pub unsafe fn compare_32(a: i32, b: i32) -> i8 {
+    compare_64(a as i64, b as i64)
+}
+
+#[inline]
+pub unsafe fn compare_64(a: i64, b: i64) -> i8 {
+    let (gt, lt): (u8, u8);
+    asm!(
+        "cmp {a}, {b}",
+        "setg {gt}",
+        "setl {lt}",
+        a = in(reg) a,
+        b = in(reg) b,
+        gt = out(reg_byte) gt,
+        lt = out(reg_byte) lt,
+        options(pure, nomem, nostack),
+    );
+    gt as i8 - lt as i8
+}
+
+#[inline]
+pub unsafe fn compare_u64(a: u64, b: u64) -> i8 {
+    let (gt, lt): (u8, u8);
+    asm!(
+        "cmp {a}, {b}",
+        "seta {gt}",
+        "setb {lt}",
+        a = in(reg) a,
+        b = in(reg) b,
+        gt = out(reg_byte) gt,
+        lt = out(reg_byte) lt,
+        options(pure, nomem, nostack),
+    );
+    gt as i8 - lt as i8
+}
@@ -42,9 +42,26 @@ int8_t compare_32(int32_t a, int32_t b)
     return ((aa - bb) >> 63) - ((bb - aa) >> 63);
 }
 
+// __int128 is not available on 32-bit targets, so the 64-bit comparisons use
+// the branch-free less-than formulas from Hacker's Delight (2-12) instead of
+// widening.
+static uint64_t less_64(uint64_t a, uint64_t b)
+{
+    uint64_t d = a - b;
+    return (d ^ ((a ^ b) & (d ^ a))) >> 63;
+}
+
+static uint64_t less_u64(uint64_t a, uint64_t b)
+{
+    return ((~a & b) | (~(a ^ b) & (a - b))) >> 63;
+}
+
 int8_t compare_64(int64_t a, int64_t b)
 {
-    __int128_t aa = (__int128_t)a;
-    __int128_t bb = (__int128_t)b;
-    return ((aa - bb) >> 127) - ((bb - aa) >> 127);
+    return (int8_t)less_64(b, a) - (int8_t)less_64(a, b);
+}
+
+int8_t compare_u64(uint64_t a, uint64_t b)
+{
+    return (int8_t)less_u64(b, a) - (int8_t)less_u64(a, b);
 }
\ No newline at end of file
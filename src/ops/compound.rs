@@ -0,0 +1,158 @@
+// ObliviousOps for arrays, tuples and Option, built from the impls of their
+// elements. Equality is combined without short circuiting and comparisons are
+// lexicographic, with every element compared regardless of where the first
+// difference is.
+use super::{swap, Condition, ObliviousOps};
+
+// Combines two comparisons lexicographically: prev decides unless it is 0.
+pub fn then(prev: i8, next: i8) -> i8 {
+    i8::oselect(i8::oequal(&prev, &0), next, prev)
+}
+
+impl<T: ObliviousOps, const N: usize> ObliviousOps for [T; N] {
+    fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+        let mut b = b.into_iter();
+        a.map(|a_elem| T::oselect(cond, a_elem, b.next().unwrap()))
+    }
+
+    fn oequal(a: &Self, b: &Self) -> Condition {
+        a.iter()
+            .zip(b.iter())
+            .fold(Condition::TRUE, |acc, (a_elem, b_elem)| {
+                acc & T::oequal(a_elem, b_elem)
+            })
+    }
+
+    fn ocompare(a: &Self, b: &Self) -> i8 {
+        a.iter().zip(b.iter()).fold(0, |acc, (a_elem, b_elem)| {
+            then(acc, T::ocompare(a_elem, b_elem))
+        })
+    }
+}
+
+macro_rules! impl_ops_tuple {
+    ($($t: ident $i: tt),+) => {
+        impl<$($t: ObliviousOps),+> ObliviousOps for ($($t,)+) {
+            fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+                ($($t::oselect(cond, a.$i, b.$i),)+)
+            }
+
+            fn oequal(a: &Self, b: &Self) -> Condition {
+                Condition::TRUE $(& $t::oequal(&a.$i, &b.$i))+
+            }
+
+            fn ocompare(a: &Self, b: &Self) -> i8 {
+                let mut res = 0;
+                $(res = then(res, $t::ocompare(&a.$i, &b.$i));)+
+                res
+            }
+        }
+    };
+}
+
+impl_ops_tuple!(A 0);
+impl_ops_tuple!(A 0, B 1);
+impl_ops_tuple!(A 0, B 1, C 2);
+impl_ops_tuple!(A 0, B 1, C 2, D 3);
+impl_ops_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_ops_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_ops_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_ops_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+// Options never branch on their tag. Selection conditionally swaps the whole
+// value, tag included. Equality and comparison first move the payload out with
+// a conditional swap against Some(T::default()), so the payload read is the
+// same whether the option is Some or None. None orders before Some, as in std.
+impl<T: ObliviousOps + Default + Copy> ObliviousOps for Option<T> {
+    fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+        let (mut a, mut b) = (a, b);
+        swap(cond, &mut a, &mut b);
+        b
+    }
+
+    fn oequal(a: &Self, b: &Self) -> Condition {
+        let (a_tag, a_val) = untag(a);
+        let (b_tag, b_val) = untag(b);
+        Condition::oequal(&a_tag, &b_tag) & (!a_tag | T::oequal(&a_val, &b_val))
+    }
+
+    fn ocompare(a: &Self, b: &Self) -> i8 {
+        let (a_tag, a_val) = untag(a);
+        let (b_tag, b_val) = untag(b);
+        let val = i8::oselect(a_tag & b_tag, T::ocompare(&a_val, &b_val), 0);
+        then(Condition::ocompare(&a_tag, &b_tag), val)
+    }
+}
+
+// Returns whether opt is Some and its payload, or T::default() for None.
+fn untag<T: Default + Copy>(opt: &Option<T>) -> (Condition, T) {
+    let tag = Condition::from(opt.is_some());
+    let mut val = Some(T::default());
+    let mut opt = *opt;
+    swap(tag, &mut val, &mut opt);
+
+    // val is Some on both paths, so this never panics.
+    (tag, val.unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_array() {
+        let (a, b) = ([1u8, 2, 3], [1u8, 3, 0]);
+
+        assert_eq!(<[u8; 3]>::oselect(Condition::TRUE, a, b), a);
+        assert_eq!(<[u8; 3]>::oselect(Condition::FALSE, a, b), b);
+
+        assert!(bool::from(<[u8; 3]>::oequal(&a, &a)));
+        assert!(!bool::from(<[u8; 3]>::oequal(&a, &b)));
+
+        assert_eq!(<[u8; 3]>::ocompare(&a, &b), -1);
+        assert_eq!(<[u8; 3]>::ocompare(&b, &a), 1);
+        assert_eq!(<[u8; 3]>::ocompare(&a, &a), 0);
+        assert_eq!(<[u8; 0]>::ocompare(&[], &[]), 0);
+    }
+
+    #[test]
+    fn test_tuple() {
+        let (a, b) = ((1u64, -2i32, true), (1u64, -3i32, false));
+
+        assert_eq!(<(u64, i32, bool)>::oselect(Condition::TRUE, a, b), a);
+        assert_eq!(<(u64, i32, bool)>::oselect(Condition::FALSE, a, b), b);
+
+        assert!(bool::from(<(u64, i32, bool)>::oequal(&a, &a)));
+        assert!(!bool::from(<(u64, i32, bool)>::oequal(&a, &b)));
+
+        assert_eq!(<(u64, i32, bool)>::ocompare(&a, &b), 1);
+        assert_eq!(<(u64, i32, bool)>::ocompare(&b, &a), -1);
+
+        let (c, d) = (
+            (1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8),
+            (1, 2, 3, 4, 5, 6, 7, 9),
+        );
+        assert!(!bool::from(<(u8, u8, u8, u8, u8, u8, u8, u8)>::oequal(
+            &c, &d
+        )));
+        assert_eq!(<(u8, u8, u8, u8, u8, u8, u8, u8)>::ocompare(&c, &d), -1);
+    }
+
+    #[test]
+    fn test_option() {
+        let cases = [None, Some(0u64), Some(1), Some(u64::MAX)];
+
+        for a in cases {
+            for b in cases {
+                assert_eq!(Option::oselect(Condition::TRUE, a, b), a);
+                assert_eq!(Option::oselect(Condition::FALSE, a, b), b);
+                assert_eq!(bool::from(Option::oequal(&a, &b)), a == b);
+                assert_eq!(Option::ocompare(&a, &b), a.cmp(&b) as i8);
+            }
+        }
+
+        let (a, b) = (Some(['a', 'b']), None);
+        assert_eq!(Option::oselect(Condition::FALSE, a, b), b);
+        assert_eq!(Option::ocompare(&a, &b), 1);
+    }
+}
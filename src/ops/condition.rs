@@ -0,0 +1,135 @@
+use std::hint::black_box;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+// A secret boolean, stored as a u8 that is always 0 or 1. Like subtle's Choice,
+// values pass through black_box when they are created so the compiler cannot
+// tell that they are booleans and turn arithmetic on them back into branches.
+// The operators are bitwise and never short circuit.
+//
+// Converting back to a bool reveals the condition, so only do that for values
+// that are meant to be public.
+#[derive(Clone, Copy, Debug)]
+pub struct Condition(u8);
+
+impl Condition {
+    pub const TRUE: Condition = Condition(1);
+    pub const FALSE: Condition = Condition(0);
+
+    // Returns 1 if the condition holds and 0 otherwise.
+    #[inline]
+    pub fn as_u8(self) -> u8 {
+        self.0
+    }
+}
+
+impl From<bool> for Condition {
+    #[inline]
+    fn from(b: bool) -> Self {
+        Condition(black_box(b as u8))
+    }
+}
+
+impl From<u8> for Condition {
+    // Only the lowest bit is kept. A value other than 0 or 1 would otherwise
+    // swap some bits and not others, and reach the C functions as a bool.
+    #[inline]
+    fn from(b: u8) -> Self {
+        Condition(black_box(b & 1))
+    }
+}
+
+impl From<Condition> for bool {
+    #[inline]
+    fn from(c: Condition) -> Self {
+        c.0 != 0
+    }
+}
+
+impl BitAnd for Condition {
+    type Output = Condition;
+
+    #[inline]
+    fn bitand(self, rhs: Self) -> Self {
+        Condition(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for Condition {
+    type Output = Condition;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self {
+        Condition(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for Condition {
+    type Output = Condition;
+
+    #[inline]
+    fn bitxor(self, rhs: Self) -> Self {
+        Condition(self.0 ^ rhs.0)
+    }
+}
+
+impl Not for Condition {
+    type Output = Condition;
+
+    #[inline]
+    fn not(self) -> Self {
+        Condition(self.0 ^ 1)
+    }
+}
+
+impl BitAndAssign for Condition {
+    #[inline]
+    fn bitand_assign(&mut self, rhs: Self) {
+        *self = *self & rhs;
+    }
+}
+
+impl BitOrAssign for Condition {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = *self | rhs;
+    }
+}
+
+impl BitXorAssign for Condition {
+    #[inline]
+    fn bitxor_assign(&mut self, rhs: Self) {
+        *self = *self ^ rhs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition() {
+        for a in [false, true] {
+            for b in [false, true] {
+                let (x, y) = (Condition::from(a), Condition::from(b));
+                assert_eq!(bool::from(x & y), a & b);
+                assert_eq!(bool::from(x | y), a | b);
+                assert_eq!(bool::from(x ^ y), a ^ b);
+                assert_eq!(bool::from(!x), !a);
+                assert_eq!(x.as_u8(), a as u8);
+            }
+        }
+
+        assert!(bool::from(Condition::TRUE));
+        assert!(!bool::from(Condition::FALSE));
+        assert!(bool::from(Condition::from(1u8)));
+        assert_eq!(Condition::from(2u8).as_u8(), 0);
+        assert_eq!(Condition::from(0xffu8).as_u8(), 1);
+    }
+
+    #[test]
+    fn test_wide_condition_swap() {
+        let (mut a, mut b) = (vec![1u8; 3], vec![2u8; 5]);
+        crate::ops::swap(Condition::from(2u8), &mut a, &mut b);
+        assert_eq!((a.len(), b.len()), (3, 5));
+    }
+}
@@ -1,50 +1,210 @@
+#[cfg(feature = "asm-backend")]
+mod asm;
+mod compound;
+mod condition;
+#[cfg(target_arch = "x86_64")]
+mod simd;
+#[cfg(feature = "subtle")]
+mod subtle;
 mod swap;
 use std::cmp::Ordering;
 
+pub use compound::then;
+pub use condition::Condition;
+#[cfg(feature = "subtle")]
+pub use subtle::Subtle;
 pub use swap::swap;
 // pub use swap::ObliviousSwap;
 
 pub trait ObliviousOps {
-    fn oselect(cond: bool, a: Self, b: Self) -> Self;
+    fn oselect(cond: Condition, a: Self, b: Self) -> Self;
+    fn oequal(a: &Self, b: &Self) -> Condition;
+    fn ocompare(a: &Self, b: &Self) -> i8;
 }
 
+// The ordering used by the sorting networks. It is derived from `ocompare` so
+// that comparisons go through the C routines rather than a user's `Ord` impl.
+pub trait ObliviousOrd {
+    fn olt(a: &Self, b: &Self) -> Condition;
+}
+
+impl<T: ObliviousOps> ObliviousOrd for T {
+    fn olt(a: &Self, b: &Self) -> Condition {
+        is_less(T::ocompare(a, b))
+    }
+}
+
+// Turns the result of an ocompare-style comparison into whether a < b. -1 is the
+// only result with the sign bit set.
+pub fn is_less(cmp: i8) -> Condition {
+    Condition::from((cmp as u8) >> 7)
+}
+
+#[cfg(all(feature = "asm-backend", not(target_arch = "x86_64")))]
+compile_error!("the `asm-backend` feature is only available on x86_64");
+
+#[cfg(feature = "asm-backend")]
+use asm::{
+    compare_16, compare_32, compare_64, compare_8, compare_u64, equal_16, equal_32, equal_64,
+    equal_8, select_16, select_32, select_64, select_8,
+};
+
+#[cfg(not(feature = "asm-backend"))]
 #[link(name = "ops", kind = "static")]
 unsafe extern "C" {
-    unsafe fn select_8(cond: bool, a: i8, b: i8) -> i8;
-    unsafe fn select_16(cond: bool, a: i16, b: i16) -> i16;
-    unsafe fn select_32(cond: bool, a: i32, b: i32) -> i32;
-    unsafe fn select_64(cond: bool, a: i64, b: i64) -> i64;
+    unsafe fn select_8(cond: u8, a: i8, b: i8) -> i8;
+    unsafe fn select_16(cond: u8, a: i16, b: i16) -> i16;
+    unsafe fn select_32(cond: u8, a: i32, b: i32) -> i32;
+    unsafe fn select_64(cond: u8, a: i64, b: i64) -> i64;
+
+    unsafe fn equal_8(a: i8, b: i8) -> bool;
+    unsafe fn equal_16(a: i16, b: i16) -> bool;
+    unsafe fn equal_32(a: i32, b: i32) -> bool;
+    unsafe fn equal_64(a: i64, b: i64) -> bool;
+
+    unsafe fn compare_8(a: i8, b: i8) -> i8;
+    unsafe fn compare_16(a: i16, b: i16) -> i8;
+    unsafe fn compare_32(a: i32, b: i32) -> i8;
+    unsafe fn compare_64(a: i64, b: i64) -> i8;
+    unsafe fn compare_u64(a: u64, b: u64) -> i8;
 }
 
 // This implements ObliviousOps for primitive types by calling out to C
-// functions. I decided to call to C because 1) Rust does not allow many of the
-// operations that these bit manipulations require (overflow, casting) and 2) I
-// was unsure if the Rust workarounds would actually be constant time
-// (wrapping_sub, try_into, etc.).
+// functions (or their inline assembly counterparts with `asm-backend`). I
+// decided to call to C because 1) Rust does not allow many of the operations
+// that these bit manipulations require (overflow, casting) and 2) I was unsure
+// if the Rust workarounds would actually be constant time (wrapping_sub,
+// try_into, etc.).
+//
+// Comparisons of unsigned types are widened to the next signed type so that the
+// high bit is not read as a sign bit.
 macro_rules! impl_ops {
-    ($from: ty, $into: ty, $select_fn: expr) => {
+    ($from: ty, $into: ty, $select_fn: expr, $equal_fn: expr, $cmp_into: ty, $compare_fn: expr) => {
         impl ObliviousOps for $from {
-            fn oselect(cond: bool, a: Self, b: Self) -> Self {
-                unsafe { $select_fn(cond, a as $into, b as $into) as Self }
+            fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+                unsafe { $select_fn(cond.as_u8(), a as $into, b as $into) as Self }
+            }
+
+            fn oequal(a: &Self, b: &Self) -> Condition {
+                Condition::from(unsafe { $equal_fn(*a as $into, *b as $into) })
+            }
+
+            fn ocompare(a: &Self, b: &Self) -> i8 {
+                unsafe { $compare_fn(*a as $cmp_into, *b as $cmp_into) }
             }
         }
     };
 }
 
-impl_ops!(i8, i8, select_8);
-impl_ops!(u8, i8, select_8);
-impl_ops!(i16, i16, select_16);
-impl_ops!(u16, i16, select_16);
-impl_ops!(i32, i32, select_32);
-impl_ops!(u32, i32, select_32);
-impl_ops!(i64, i64, select_64);
-impl_ops!(u64, i64, select_64);
-impl_ops!(isize, i64, select_64); // TODO this should be arch dependent.
-impl_ops!(usize, i64, select_64); // TODO this should be arch dependent.
+impl_ops!(i8, i8, select_8, equal_8, i8, compare_8);
+impl_ops!(u8, i8, select_8, equal_8, i16, compare_16);
+impl_ops!(i16, i16, select_16, equal_16, i16, compare_16);
+impl_ops!(u16, i16, select_16, equal_16, i32, compare_32);
+impl_ops!(i32, i32, select_32, equal_32, i32, compare_32);
+impl_ops!(u32, i32, select_32, equal_32, i64, compare_64);
+impl_ops!(i64, i64, select_64, equal_64, i64, compare_64);
+impl_ops!(u64, i64, select_64, equal_64, u64, compare_u64);
+
+#[cfg(target_pointer_width = "32")]
+impl_ops!(isize, i32, select_32, equal_32, i32, compare_32);
+#[cfg(target_pointer_width = "32")]
+impl_ops!(usize, i32, select_32, equal_32, i64, compare_64);
+#[cfg(target_pointer_width = "64")]
+impl_ops!(isize, i64, select_64, equal_64, i64, compare_64);
+#[cfg(target_pointer_width = "64")]
+impl_ops!(usize, i64, select_64, equal_64, u64, compare_u64);
+
+// 128-bit values are handled as two 64-bit halves. The halves are always both
+// selected and compared, and the comparison of the low halves (which is always
+// unsigned) only decides the result when the high halves are equal.
+fn select_128(cond: Condition, a: u128, b: u128) -> u128 {
+    let hi = unsafe { select_64(cond.as_u8(), (a >> 64) as i64, (b >> 64) as i64) } as u64;
+    let lo = unsafe { select_64(cond.as_u8(), a as i64, b as i64) } as u64;
+    ((hi as u128) << 64) | lo as u128
+}
+
+macro_rules! impl_ops_128 {
+    ($t: ty, $hi: ty, $compare_hi_fn: expr) => {
+        impl ObliviousOps for $t {
+            fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+                select_128(cond, a as u128, b as u128) as Self
+            }
+
+            fn oequal(a: &Self, b: &Self) -> Condition {
+                let (a, b) = (*a as u128, *b as u128);
+                let hi = unsafe { equal_64((a >> 64) as i64, (b >> 64) as i64) };
+                let lo = unsafe { equal_64(a as i64, b as i64) };
+                Condition::from(hi) & Condition::from(lo)
+            }
+
+            fn ocompare(a: &Self, b: &Self) -> i8 {
+                unsafe {
+                    let hi = $compare_hi_fn((*a >> 64) as $hi, (*b >> 64) as $hi);
+                    let lo = compare_u64(*a as u64, *b as u64);
+                    select_8(equal_8(hi, 0) as u8, lo, hi)
+                }
+            }
+        }
+    };
+}
+
+impl_ops_128!(i128, i64, compare_64);
+impl_ops_128!(u128, u64, compare_u64);
+
+impl ObliviousOps for Condition {
+    fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+        Condition::from(unsafe { select_8(cond.as_u8(), a.as_u8() as i8, b.as_u8() as i8) } as u8)
+    }
+
+    fn oequal(a: &Self, b: &Self) -> Condition {
+        !(*a ^ *b)
+    }
+
+    fn ocompare(a: &Self, b: &Self) -> i8 {
+        unsafe { compare_8(a.as_u8() as i8, b.as_u8() as i8) }
+    }
+}
+
+impl ObliviousOps for bool {
+    fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+        unsafe { select_8(cond.as_u8(), a as i8, b as i8) != 0 }
+    }
+
+    fn oequal(a: &Self, b: &Self) -> Condition {
+        Condition::from(unsafe { equal_8(*a as i8, *b as i8) })
+    }
+
+    fn ocompare(a: &Self, b: &Self) -> i8 {
+        unsafe { compare_8(*a as i8, *b as i8) }
+    }
+}
+
+impl ObliviousOps for char {
+    fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+        // The result is always one of the two inputs, so it is a valid char.
+        unsafe { char::from_u32_unchecked(select_32(cond.as_u8(), a as i32, b as i32) as u32) }
+    }
+
+    fn oequal(a: &Self, b: &Self) -> Condition {
+        Condition::from(unsafe { equal_32(*a as i32, *b as i32) })
+    }
+
+    fn ocompare(a: &Self, b: &Self) -> i8 {
+        unsafe { compare_64(*a as i64, *b as i64) }
+    }
+}
 
 impl ObliviousOps for Ordering {
-    fn oselect(cond: bool, a: Self, b: Self) -> Self {
-        unsafe { select_8(cond, a as i8, b as i8).cmp(&0) }
+    fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+        unsafe { select_8(cond.as_u8(), a as i8, b as i8).cmp(&0) }
+    }
+
+    fn oequal(a: &Self, b: &Self) -> Condition {
+        Condition::from(unsafe { equal_8(*a as i8, *b as i8) })
+    }
+
+    fn ocompare(a: &Self, b: &Self) -> i8 {
+        unsafe { compare_8(*a as i8, *b as i8) }
     }
 }
 
@@ -56,8 +216,8 @@ mod tests {
     fn test_select() {
         macro_rules! test_select {
             ($t: ty, $a: expr, $b: expr) => {
-                assert_eq!(<$t>::oselect(true, $a, $b), $a);
-                assert_eq!(<$t>::oselect(false, $a, $b), $b);
+                assert_eq!(<$t>::oselect(Condition::TRUE, $a, $b), $a);
+                assert_eq!(<$t>::oselect(Condition::FALSE, $a, $b), $b);
             };
         }
 
@@ -66,15 +226,155 @@ mod tests {
         test_select!(i32, -2, 1);
         test_select!(i64, -2, 1);
         test_select!(isize, -2, 1);
+        test_select!(i128, -2, 1);
+        test_select!(i128, i128::MIN, i128::MAX);
 
         test_select!(u8, 2, 1);
         test_select!(u16, 2, 1);
         test_select!(u32, 2, 1);
         test_select!(u64, 2, 1);
         test_select!(usize, 2, 1);
+        test_select!(u128, 2, 1);
+        test_select!(u128, u128::MAX, 1 << 64);
+
+        test_select!(bool, true, false);
+        for (a, b) in [(false, true), (true, false)] {
+            let (x, y) = (Condition::from(a), Condition::from(b));
+            assert_eq!(bool::from(Condition::oselect(Condition::TRUE, x, y)), a);
+            assert_eq!(bool::from(Condition::oselect(Condition::FALSE, x, y)), b);
+        }
+        test_select!(char, 'a', '\u{10FFFF}');
 
         test_select!(Ordering, Ordering::Equal, Ordering::Less);
         test_select!(Ordering, Ordering::Equal, Ordering::Greater);
         test_select!(Ordering, Ordering::Less, Ordering::Greater);
     }
+
+    #[test]
+    fn test_equal() {
+        macro_rules! test_equal {
+            ($t: ty, $a: expr, $b: expr) => {
+                assert!(bool::from(<$t>::oequal(&$a, &$a)));
+                assert!(!bool::from(<$t>::oequal(&$a, &$b)));
+                assert!(!bool::from(<$t>::oequal(&$b, &$a)));
+            };
+        }
+
+        test_equal!(i8, -2, 1);
+        test_equal!(i16, -2, 1);
+        test_equal!(i32, -2, 1);
+        test_equal!(i64, -2, 1);
+        test_equal!(isize, -2, 1);
+        test_equal!(i128, -2, 1);
+        test_equal!(i128, 1 << 64, 1);
+
+        test_equal!(u8, u8::MAX, 1);
+        test_equal!(u16, u16::MAX, 1);
+        test_equal!(u32, u32::MAX, 1);
+        test_equal!(u64, u64::MAX, 1);
+        test_equal!(usize, usize::MAX, 1);
+        test_equal!(u128, u128::MAX, 1);
+        test_equal!(u128, 1 << 64, 1);
+
+        test_equal!(bool, true, false);
+        test_equal!(char, 'a', 'b');
+
+        test_equal!(Ordering, Ordering::Less, Ordering::Greater);
+    }
+
+    #[test]
+    fn test_compare() {
+        macro_rules! test_compare {
+            ($t: ty, $a: expr, $b: expr) => {
+                assert_eq!(<$t>::ocompare(&$a, &$b), 1);
+                assert_eq!(<$t>::ocompare(&$b, &$a), -1);
+                assert_eq!(<$t>::ocompare(&$a, &$a), 0);
+            };
+        }
+
+        test_compare!(i8, i8::MAX, i8::MIN);
+        test_compare!(i16, i16::MAX, i16::MIN);
+        test_compare!(i32, i32::MAX, i32::MIN);
+        test_compare!(i64, i64::MAX, i64::MIN);
+        test_compare!(isize, isize::MAX, isize::MIN);
+        test_compare!(i128, i128::MAX, i128::MIN);
+        test_compare!(i128, -1, -2);
+        test_compare!(i128, 1 << 64, u64::MAX as i128);
+        test_compare!(i128, -(1 << 64), -(1 << 64) - 1);
+
+        test_compare!(u8, u8::MAX, 0);
+        test_compare!(u16, u16::MAX, 0);
+        test_compare!(u32, u32::MAX, 0);
+        test_compare!(u64, u64::MAX, 0);
+        test_compare!(usize, usize::MAX, 0);
+        test_compare!(u128, u128::MAX, 0);
+        test_compare!(u128, 1 << 64, u64::MAX as u128);
+        test_compare!(u128, (1 << 64) | (1 << 63), 1 << 64);
+
+        test_compare!(i64, -1, -2);
+        test_compare!(i64, 1, -1);
+        test_compare!(u64, 1 << 63, (1 << 63) - 1);
+
+        test_compare!(bool, true, false);
+        test_compare!(char, '\u{10FFFF}', 'a');
+
+        test_compare!(Ordering, Ordering::Greater, Ordering::Less);
+    }
+
+    #[test]
+    fn test_lt() {
+        assert!(bool::from(i64::olt(&-1, &0)));
+        assert!(!bool::from(i64::olt(&0, &0)));
+        assert!(!bool::from(i64::olt(&0, &-1)));
+        assert!(bool::from(u64::olt(&0, &u64::MAX)));
+        assert!(!bool::from(u64::olt(&u64::MAX, &0)));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive() {
+        #[derive(crate::ObliviousOps, Debug, PartialEq, Clone, Copy)]
+        struct Record {
+            #[oblivious(key)]
+            major: u32,
+            payload: u64,
+            #[oblivious(key)]
+            minor: i16,
+        }
+
+        #[derive(crate::ObliviousOps, Debug, PartialEq, Clone, Copy)]
+        struct Pair<K>(K, u8);
+
+        let a = Record {
+            major: 1,
+            payload: 7,
+            minor: -3,
+        };
+        let b = Record {
+            major: 1,
+            payload: 9,
+            minor: 2,
+        };
+        let c = Record {
+            major: 0,
+            payload: 7,
+            minor: 5,
+        };
+
+        assert_eq!(Record::oselect(Condition::TRUE, a, b), a);
+        assert_eq!(Record::oselect(Condition::FALSE, a, b), b);
+
+        assert!(bool::from(Record::oequal(&a, &a)));
+        assert!(!bool::from(Record::oequal(&a, &b)));
+
+        assert_eq!(Record::ocompare(&a, &b), -1);
+        assert_eq!(Record::ocompare(&b, &a), 1);
+        assert_eq!(Record::ocompare(&a, &c), 1);
+        assert_eq!(Record::ocompare(&a, &Record { payload: 0, ..a }), 0);
+
+        let (p, q) = (Pair(-1i64, 2), Pair(-1i64, 1));
+        assert_eq!(Pair::oselect(Condition::FALSE, p, q), q);
+        assert!(!bool::from(Pair::oequal(&p, &q)));
+        assert_eq!(Pair::ocompare(&p, &q), 1);
+    }
 }
@@ -0,0 +1,102 @@
+// Vectorised conditional swaps for large elements. Each instruction moves 32
+// (AVX2) or 64 (AVX-512) bytes, and the blend is driven by a mask built from
+// cond with arithmetic only, so the same loads, blends and stores run whether or
+// not the elements are swapped.
+//
+// Like the scalar swaps in asm.rs, the loads and stores are done inside the
+// assembly. Elements this large often have padding, which may be uninitialized,
+// so their bytes must never be read as integer vectors in Rust.
+//
+// The backend is picked from the target features the crate was compiled with,
+// falling back to runtime detection. The choice depends only on the CPU, never
+// on the data.
+use std::arch::asm;
+use std::arch::x86_64::__m256i;
+
+pub const AVX2_BLOCK: usize = 32;
+pub const AVX512_BLOCK: usize = 64;
+
+#[inline]
+pub fn has_avx2() -> bool {
+    cfg!(target_feature = "avx2") || std::is_x86_feature_detected!("avx2")
+}
+
+#[inline]
+pub fn has_avx512() -> bool {
+    cfg!(target_feature = "avx512f") || std::is_x86_feature_detected!("avx512f")
+}
+
+// Swaps the first `blocks * AVX2_BLOCK` bytes of a and b when cond is 1.
+//
+// SAFETY: a and b must be valid for `blocks * AVX2_BLOCK` bytes and the CPU must
+// support AVX2.
+#[target_feature(enable = "avx2")]
+pub unsafe fn swap_avx2(cond: u8, a: *mut u8, b: *mut u8, blocks: usize) {
+    // Every byte of the mask is 0xff when cond is 1 and 0 otherwise.
+    let mask: __m256i;
+    asm!(
+        "movzx {t:e}, {cond}",
+        "neg {t:e}",
+        "vmovd {mask:x}, {t:e}",
+        "vpbroadcastb {mask}, {mask:x}",
+        cond = in(reg_byte) cond,
+        t = out(reg) _,
+        mask = out(ymm_reg) mask,
+        options(pure, nomem, nostack),
+    );
+
+    for i in 0..blocks {
+        asm!(
+            "vmovdqu {x}, ymmword ptr [{a}]",
+            "vmovdqu {y}, ymmword ptr [{b}]",
+            "vpblendvb {t}, {x}, {y}, {mask}",
+            "vpblendvb {y}, {y}, {x}, {mask}",
+            "vmovdqu ymmword ptr [{a}], {t}",
+            "vmovdqu ymmword ptr [{b}], {y}",
+            mask = in(ymm_reg) mask,
+            a = in(reg) a.add(i * AVX2_BLOCK),
+            b = in(reg) b.add(i * AVX2_BLOCK),
+            x = out(ymm_reg) _,
+            y = out(ymm_reg) _,
+            t = out(ymm_reg) _,
+            options(nostack),
+        );
+    }
+}
+
+// Swaps the first `blocks * AVX512_BLOCK` bytes of a and b when cond is 1.
+//
+// SAFETY: a and b must be valid for `blocks * AVX512_BLOCK` bytes and the CPU
+// must support AVX-512F.
+#[target_feature(enable = "avx512f")]
+pub unsafe fn swap_avx512(cond: u8, a: *mut u8, b: *mut u8, blocks: usize) {
+    // Every bit of the mask is set when cond is 1 and clear otherwise.
+    let mask: u16;
+    asm!(
+        "movzx {t:e}, {cond}",
+        "neg {t:e}",
+        "kmovw {mask}, {t:e}",
+        cond = in(reg_byte) cond,
+        t = out(reg) _,
+        mask = out(kreg) mask,
+        options(pure, nomem, nostack),
+    );
+
+    for i in 0..blocks {
+        asm!(
+            "vmovdqu64 {x}, zmmword ptr [{a}]",
+            "vmovdqu64 {y}, zmmword ptr [{b}]",
+            "vpblendmq {t} {{{mask}}}, {x}, {y}",
+            "vpblendmq {y} {{{mask}}}, {y}, {x}",
+            "vmovdqu64 zmmword ptr [{a}], {t}",
+            "vmovdqu64 zmmword ptr [{b}], {y}",
+            mask = in(kreg) mask,
+            a = in(reg) a.add(i * AVX512_BLOCK),
+            b = in(reg) b.add(i * AVX512_BLOCK),
+            x = out(zmm_reg) _,
+            y = out(zmm_reg) _,
+            t = out(zmm_reg) _,
+            options(nostack),
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// Bridges to the subtle crate, enabled with the `subtle` feature.
+//
+// A blanket ObliviousOps impl over subtle's traits would overlap with the impls
+// for primitives (subtle implements its traits for them too), so subtle types
+// are used through the Subtle wrapper instead. It is repr(transparent), so a
+// slice of T can be viewed as a slice of Subtle<T> without copying.
+//
+// This is narrower than a bridge for every T: ConditionallySelectable +
+// ConstantTimeEq. ObliviousOps includes ocompare, so Subtle<T> also needs
+// ConstantTimeGreater, which subtle only implements for unsigned integers.
+// Signed integers and crypto types, like curve points and scalars, get no
+// ObliviousOps and cannot be sorted. They can still be compacted and shuffled,
+// since those only move elements with swap.
+use super::{Condition, ObliviousOps};
+use ::subtle::{Choice, ConditionallySelectable, ConstantTimeEq, ConstantTimeGreater};
+
+impl From<Choice> for Condition {
+    #[inline]
+    fn from(c: Choice) -> Self {
+        Condition::from(c.unwrap_u8())
+    }
+}
+
+impl From<Condition> for Choice {
+    #[inline]
+    fn from(c: Condition) -> Self {
+        Choice::from(c.as_u8())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(transparent)]
+pub struct Subtle<T>(pub T);
+
+impl<T> Subtle<T> {
+    pub fn from_slice(data: &[T]) -> &[Subtle<T>] {
+        // Subtle<T> is repr(transparent) over T.
+        unsafe { std::slice::from_raw_parts(data.as_ptr() as *const Subtle<T>, data.len()) }
+    }
+
+    pub fn from_slice_mut(data: &mut [T]) -> &mut [Subtle<T>] {
+        // Subtle<T> is repr(transparent) over T.
+        unsafe { std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut Subtle<T>, data.len()) }
+    }
+}
+
+impl<T> ObliviousOps for Subtle<T>
+where
+    T: ConditionallySelectable + ConstantTimeEq + ConstantTimeGreater,
+{
+    fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+        Subtle(T::conditional_select(&b.0, &a.0, cond.into()))
+    }
+
+    fn oequal(a: &Self, b: &Self) -> Condition {
+        a.0.ct_eq(&b.0).into()
+    }
+
+    fn ocompare(a: &Self, b: &Self) -> i8 {
+        let gt = a.0.ct_gt(&b.0).unwrap_u8() as i8;
+        let lt = b.0.ct_gt(&a.0).unwrap_u8() as i8;
+        gt - lt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choice() {
+        for b in [0u8, 1] {
+            assert_eq!(Condition::from(Choice::from(b)).as_u8(), b);
+            assert_eq!(Choice::from(Condition::from(b)).unwrap_u8(), b);
+        }
+    }
+
+    #[test]
+    fn test_subtle() {
+        let (a, b) = (Subtle(3u32), Subtle(u32::MAX));
+
+        assert_eq!(Subtle::oselect(Condition::TRUE, a, b).0, a.0);
+        assert_eq!(Subtle::oselect(Condition::FALSE, a, b).0, b.0);
+
+        assert!(bool::from(Subtle::oequal(&a, &a)));
+        assert!(!bool::from(Subtle::oequal(&a, &b)));
+
+        assert_eq!(Subtle::ocompare(&a, &b), -1);
+        assert_eq!(Subtle::ocompare(&b, &a), 1);
+        assert_eq!(Subtle::ocompare(&a, &a), 0);
+    }
+
+    // Stands in for a curve point: selectable and comparable for equality, but
+    // without an order, so it does not implement ObliviousOps.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    struct Point(u64, u64);
+
+    impl ConstantTimeEq for Point {
+        fn ct_eq(&self, other: &Self) -> Choice {
+            self.0.ct_eq(&other.0) & self.1.ct_eq(&other.1)
+        }
+    }
+
+    #[test]
+    fn test_subtle_unordered() {
+        let mut data: Vec<Point> = (0..100).map(|i| Point(i % 3, i)).collect();
+
+        crate::shuffle(&mut data);
+        let kept = crate::compact_by(&mut data, |p| p.0.ct_eq(&0).into());
+        assert_eq!(kept, 34);
+        assert!(data[..kept].iter().all(|p| p.0 == 0));
+
+        let mut ids: Vec<u64> = data.iter().map(|p| p.1).collect();
+        ids.sort();
+        assert_eq!(ids, (0..100).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_subtle_sort() {
+        let data: Vec<Subtle<u64>> = (0..100).rev().map(Subtle).collect();
+
+        let sorted = crate::sort(data);
+        assert!(sorted.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
+}
@@ -9,6 +9,22 @@ void swap8(bool cond, uint8_t *a, uint8_t *b)
     *a ^= *b;
 }
 
+void swap16(bool cond, uint16_t *a, uint16_t *b)
+{
+    uint16_t mask = ~((uint16_t)cond - 1);
+    *a ^= *b;
+    *b ^= *a & mask;
+    *a ^= *b;
+}
+
+void swap32(bool cond, uint32_t *a, uint32_t *b)
+{
+    uint32_t mask = ~((uint32_t)cond - 1);
+    *a ^= *b;
+    *b ^= *a & mask;
+    *a ^= *b;
+}
+
 void swap64(bool cond, uint64_t *a, uint64_t *b)
 {
     uint64_t mask = ~((uint64_t)cond - 1);
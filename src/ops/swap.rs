@@ -1,28 +1,86 @@
-// use std::arch::asm;
+#[cfg(feature = "asm-backend")]
+use super::asm::{swap16, swap32, swap64, swap8};
 
+#[cfg(not(feature = "asm-backend"))]
 #[link(name = "ops", kind = "static")]
 extern "C" {
-    // fn swap8(cond: u8, a: *mut u8, b: *mut u8);
+    fn swap8(cond: u8, a: *mut u8, b: *mut u8);
+    fn swap16(cond: u8, a: *mut u16, b: *mut u16);
+    fn swap32(cond: u8, a: *mut u32, b: *mut u32);
     fn swap64(cond: u8, a: *mut u64, b: *mut u64);
 }
 
-pub fn swap<T>(cond: bool, a: &mut T, b: &mut T) {
-    assert!(std::mem::size_of::<T>() % 8 == 0);
+#[cfg(target_arch = "x86_64")]
+use super::simd;
+use super::Condition;
 
-    let mut remaining_blocks = std::mem::size_of::<T>() / 8;
+// Elements at least this large are swapped with vector instructions when the
+// CPU supports them. The rest of the element is finished by swap_scalar.
+#[cfg(target_arch = "x86_64")]
+const SIMD_THRESHOLD: usize = simd::AVX2_BLOCK;
 
-    let mut a_ptr = a as *mut T as *mut u64;
-    let mut b_ptr = b as *mut T as *mut u64;
-    let cond = cond as u8;
+pub fn swap<T>(cond: Condition, a: &mut T, b: &mut T) {
+    let size = std::mem::size_of::<T>();
+    let a_ptr = a as *mut T as *mut u8;
+    let b_ptr = b as *mut T as *mut u8;
+    let cond = cond.as_u8();
 
-    unsafe {
-        while remaining_blocks > 0 {
-            swap64(cond, a_ptr, b_ptr);
-            a_ptr = a_ptr.add(1);
-            b_ptr = b_ptr.add(1);
-            remaining_blocks -= 1;
+    #[cfg(target_arch = "x86_64")]
+    if size >= SIMD_THRESHOLD {
+        if simd::has_avx512() {
+            let blocks = size / simd::AVX512_BLOCK;
+            unsafe {
+                simd::swap_avx512(cond, a_ptr, b_ptr, blocks);
+                swap_scalar::<T>(cond, a_ptr, b_ptr, blocks * simd::AVX512_BLOCK);
+            }
+            return;
+        } else if simd::has_avx2() {
+            let blocks = size / simd::AVX2_BLOCK;
+            unsafe {
+                simd::swap_avx2(cond, a_ptr, b_ptr, blocks);
+                swap_scalar::<T>(cond, a_ptr, b_ptr, blocks * simd::AVX2_BLOCK);
+            }
+            return;
         }
     }
+
+    unsafe { swap_scalar::<T>(cond, a_ptr, b_ptr, 0) }
+}
+
+// Swaps bytes `start..size_of::<T>()` in the widest words the alignment of T
+// allows, then finishes the tail with narrower words. Since the size of T is a
+// multiple of its alignment, every access is aligned. Size and alignment are
+// fixed per type, so the sequence of calls does not depend on the data.
+//
+// SAFETY: a and b must point to valid values of T and start must be a multiple
+// of 8.
+unsafe fn swap_scalar<T>(cond: u8, a_ptr: *mut u8, b_ptr: *mut u8, start: usize) {
+    let size = std::mem::size_of::<T>();
+    let align = std::mem::align_of::<T>();
+    let mut i = start;
+
+    if align >= 8 {
+        while i + 8 <= size {
+            swap64(cond, a_ptr.add(i) as *mut u64, b_ptr.add(i) as *mut u64);
+            i += 8;
+        }
+    }
+    if align >= 4 {
+        while i + 4 <= size {
+            swap32(cond, a_ptr.add(i) as *mut u32, b_ptr.add(i) as *mut u32);
+            i += 4;
+        }
+    }
+    if align >= 2 {
+        while i + 2 <= size {
+            swap16(cond, a_ptr.add(i) as *mut u16, b_ptr.add(i) as *mut u16);
+            i += 2;
+        }
+    }
+    while i < size {
+        swap8(cond, a_ptr.add(i), b_ptr.add(i));
+        i += 1;
+    }
 }
 
 // // slower
@@ -41,18 +99,6 @@ pub fn swap<T>(cond: bool, a: &mut T, b: &mut T) {
 //     }
 // }
 
-// unsafe fn swap64(cond: u8, a: *mut i64, b: *mut i64) {
-//     asm!(
-//         "test {cond}, {cond}",
-//         "cmovnz {a:r}, {b:r}",
-//         "cmovnz {b:r}, {tmp:r}",
-//         cond = in(reg_byte) cond,
-//         tmp = in(reg) *a,
-//         a = inout(reg) *a,
-//         b = inout(reg) *b,
-//     );
-// }
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,10 +113,10 @@ mod tests {
                 let mut a = $a as $t;
                 let mut b = $b as $t;
 
-                swap(false, &mut a, &mut b);
+                swap(Condition::FALSE, &mut a, &mut b);
                 assert_eq!((a, b), ($a, $b));
 
-                swap(true, &mut a, &mut b);
+                swap(Condition::TRUE, &mut a, &mut b);
                 assert_eq!((a, b), ($b, $a));
             };
         }
@@ -79,6 +125,86 @@ mod tests {
         test_swap!(isize, -5, 4);
         test_swap!(u64, 5, 4);
         test_swap!(usize, 5, 4);
+        test_swap!(u8, 5, 4);
+        test_swap!(i16, -5, 4);
+        test_swap!(u32, 5, 4);
+        test_swap!(i32, -5, 4);
+    }
+
+    #[test]
+    fn test_swap_odd_sizes() {
+        macro_rules! test_swap {
+            ($a: expr, $b: expr) => {
+                let (mut a, mut b) = ($a, $b);
+
+                swap(Condition::FALSE, &mut a, &mut b);
+                assert_eq!((a, b), ($a, $b));
+
+                swap(Condition::TRUE, &mut a, &mut b);
+                assert_eq!((a, b), ($b, $a));
+            };
+        }
+
+        #[repr(C, packed)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Packed {
+            a: u64,
+            b: u8,
+            c: u16,
+        }
+
+        test_swap!((1u32, 2u16), (3u32, 4u16));
+        test_swap!((1u16, 2u8), (3u16, 4u8));
+        test_swap!([1u8; 20], [2u8; 20]);
+        test_swap!([1u8; 3], [2u8; 3]);
+        test_swap!([1u16; 7], [2u16; 7]);
+        test_swap!([1u32; 5], [2u32; 5]);
+        test_swap!((1u64, 2u32), (3u64, 4u32));
+        test_swap!(
+            Packed { a: 1, b: 2, c: 3 },
+            Packed {
+                a: u64::MAX,
+                b: 5,
+                c: 6
+            }
+        );
+        test_swap!((), ());
+        test_swap!(Some(1u32), None::<u32>);
+        test_swap!(None::<(u8, u64)>, Some((7u8, 8u64)));
+        test_swap!([1u8; 33], [2u8; 33]);
+        test_swap!([1u64; 9], [2u64; 9]);
+        test_swap!([1u32; 23], [2u32; 23]);
+        // Padded elements large enough for the vector swaps.
+        test_swap!(Some([1u64; 4]), None::<[u64; 4]>);
+        test_swap!([(1u8, 2u64); 5], [(3u8, 4u64); 5]);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_simd_swap() {
+        macro_rules! test_backend {
+            ($swap_fn: expr, $block: expr) => {
+                let mut a: Vec<u8> = (0..(4 * $block)).map(|i| i as u8).collect();
+                let mut b: Vec<u8> = (0..(4 * $block)).map(|i| !(i as u8)).collect();
+                let (a_orig, b_orig) = (a.clone(), b.clone());
+
+                unsafe { $swap_fn(0, a.as_mut_ptr(), b.as_mut_ptr(), 3) };
+                assert_eq!((&a, &b), (&a_orig, &b_orig));
+
+                unsafe { $swap_fn(1, a.as_mut_ptr(), b.as_mut_ptr(), 3) };
+                assert_eq!(&a[..3 * $block], &b_orig[..3 * $block]);
+                assert_eq!(&b[..3 * $block], &a_orig[..3 * $block]);
+                assert_eq!(&a[3 * $block..], &a_orig[3 * $block..]);
+                assert_eq!(&b[3 * $block..], &b_orig[3 * $block..]);
+            };
+        }
+
+        if simd::has_avx2() {
+            test_backend!(simd::swap_avx2, simd::AVX2_BLOCK);
+        }
+        if simd::has_avx512() {
+            test_backend!(simd::swap_avx512, simd::AVX512_BLOCK);
+        }
     }
 
     const SIZE: usize = 127;
@@ -101,7 +227,60 @@ mod tests {
             _dum: [1; SIZE],
         };
 
-        bench.iter(|| swap(true, &mut a, &mut b));
+        bench.iter(|| swap(Condition::TRUE, &mut a, &mut b));
+    }
+
+    fn big_elems() -> (BigElem, BigElem) {
+        let a = BigElem {
+            _key: 0,
+            _dum: [0; SIZE],
+        };
+
+        let b = BigElem {
+            _key: 1,
+            _dum: [1; SIZE],
+        };
+
+        (a, b)
+    }
+
+    #[bench]
+    fn bench_swap_scalar(bench: &mut Bencher) {
+        let (mut a, mut b) = big_elems();
+        let a_ptr = &mut a as *mut BigElem as *mut u8;
+        let b_ptr = &mut b as *mut BigElem as *mut u8;
+
+        bench.iter(|| unsafe { swap_scalar::<BigElem>(1, a_ptr, b_ptr, 0) });
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[bench]
+    fn bench_swap_avx2(bench: &mut Bencher) {
+        if !simd::has_avx2() {
+            return;
+        }
+
+        let (mut a, mut b) = big_elems();
+        let a_ptr = &mut a as *mut BigElem as *mut u8;
+        let b_ptr = &mut b as *mut BigElem as *mut u8;
+        let blocks = std::mem::size_of::<BigElem>() / simd::AVX2_BLOCK;
+
+        bench.iter(|| unsafe { simd::swap_avx2(1, a_ptr, b_ptr, blocks) });
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[bench]
+    fn bench_swap_avx512(bench: &mut Bencher) {
+        if !simd::has_avx512() {
+            return;
+        }
+
+        let (mut a, mut b) = big_elems();
+        let a_ptr = &mut a as *mut BigElem as *mut u8;
+        let b_ptr = &mut b as *mut BigElem as *mut u8;
+        let blocks = std::mem::size_of::<BigElem>() / simd::AVX512_BLOCK;
+
+        bench.iter(|| unsafe { simd::swap_avx512(1, a_ptr, b_ptr, blocks) });
     }
 
     // #[bench]
@@ -22,14 +22,10 @@ mod tests {
         //     .build()
         //     .unwrap();
 
-        let mut data: Vec<i64> = (0..10).into_iter().collect();
+        let mut data: Vec<i64> = (0..10).collect();
         shuffle(&mut data);
-        println!("{:?}", data);
 
-        assert!(true);
-
-        //shuffle
-        //sort
-        //assert_eq
+        data.sort();
+        assert_eq!(data, (0..10).collect::<Vec<i64>>());
     }
 }
@@ -3,17 +3,18 @@ use rand::TryRngCore;
 use rayon::ThreadPool;
 
 use crate::compact;
-use crate::ops;
+use crate::ops::{self, Condition};
+use crate::ObliviousOrd;
 
-pub fn mark_half(n: usize) -> Vec<bool> {
+pub fn mark_half(n: usize) -> Vec<Condition> {
     let mut remaining_ones = n / 2;
 
     (0..n)
         .map(|i| {
             let remaining = n - i;
             let r = OsRng.try_next_u64().unwrap() as usize % remaining; // SECURITY: Not unbiased.
-            let bit = r < remaining_ones;
-            remaining_ones -= bit as usize;
+            let bit = usize::olt(&r, &remaining_ones);
+            remaining_ones -= bit.as_u8() as usize;
             bit
         })
         .collect()
@@ -46,7 +47,7 @@ pub fn or_shuffle<T>(data: &mut [T]) {
     if n < 2 {
         return;
     } else if n == 2 {
-        let cond = (OsRng.try_next_u32().unwrap() & 1) != 0;
+        let cond = Condition::from((OsRng.try_next_u32().unwrap() & 1) as u8);
 
         let (l_data, r_data) = data.split_at_mut(data.len() / 2);
         ops::swap(cond, &mut l_data[0], &mut r_data[0]);
@@ -1,17 +1,32 @@
-use crate::ops;
+use crate::ops::{self, Condition};
 use rayon::ThreadPool;
 
-// TODO: figure out why parallel_bitonic_linear_pass is slower.
-// TODO: check the number of spawned threads.
+// The parallel merge is cache blocked. A merge level with stride s compares
+// elements s apart, so a level is a sweep over the whole list while s is larger
+// than a block. Such levels are done SWEEP_LEVELS at a time: each run of 2s
+// elements is viewed as 2^SWEEP_LEVELS rows, and the levels are applied one
+// tile of columns at a time, so a tile stays in cache for all of them. Tiles
+// are independent and are split across threads. Once s fits in a block, the
+// remaining levels stay within blocks of 2s elements, which are merged
+// independently.
+const BLOCK_BYTES: usize = 1 << 18;
+const SWEEP_LEVELS: u32 = 4;
+
+// The number of elements in a block, a power of two.
+fn block_len<T>() -> usize {
+    let len = BLOCK_BYTES / std::mem::size_of::<T>().max(1);
+    1 << usize::ilog2(len.max(2))
+}
 
-pub fn parallel_bitonic_sort<T: Ord + Send>(
+pub fn parallel_bitonic_sort<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
     data: &mut [T],
     cond: bool,
+    lt: &F,
     pool: &ThreadPool,
     threads: usize,
 ) {
     if threads <= 1 || data.len() <= 1 {
-        bitonic_sort(data, cond);
+        bitonic_sort(data, cond, lt);
         return;
     }
 
@@ -20,100 +35,428 @@ pub fn parallel_bitonic_sort<T: Ord + Send>(
     let r_threads = threads - l_threads;
 
     pool.scope(|s| {
-        s.spawn(|_| parallel_bitonic_sort(l_half, cond, pool, l_threads));
-        s.spawn(|_| parallel_bitonic_sort(r_half, !cond, pool, r_threads));
+        s.spawn(|_| parallel_bitonic_sort(l_half, cond, lt, pool, l_threads));
+        s.spawn(|_| parallel_bitonic_sort(r_half, !cond, lt, pool, r_threads));
     });
-    parallel_bitonic_merge(l_half, r_half, cond, pool, threads);
+    parallel_bitonic_merge(data, cond, lt, pool, threads);
 }
 
-fn parallel_bitonic_merge<T: Ord + Send>(
-    l_half: &mut [T],
-    r_half: &mut [T],
+// Merges a bitonic list whose length is a power of two.
+fn parallel_bitonic_merge<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    data: &mut [T],
+    cond: bool,
+    lt: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let block = block_len::<T>();
+    let mut stride = data.len() / 2;
+
+    if threads > 1 {
+        while stride >= block {
+            let levels = SWEEP_LEVELS.min(usize::ilog2(stride / block) + 1);
+            let width = (2 * stride) >> levels;
+            let tile = (block >> levels).max(1);
+            parallel_merge_sweep(data, 2 * stride, width, tile, cond, lt, pool, threads);
+            stride >>= levels;
+        }
+    }
+
+    parallel_merge_blocks(data, 2 * stride, cond, lt, pool, threads);
+}
+
+// Applies the levels with strides run_len / 2 down to width to each run of
+// run_len elements.
+#[allow(clippy::too_many_arguments)]
+fn parallel_merge_sweep<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    data: &mut [T],
+    run_len: usize,
+    width: usize,
+    tile: usize,
     cond: bool,
+    lt: &F,
     pool: &ThreadPool,
     threads: usize,
 ) {
-    if threads <= 1 || l_half.len() < 1 {
-        bitonic_merge(l_half, r_half, cond);
+    if threads <= 1 {
+        for run in data.chunks_mut(run_len) {
+            sweep_columns(run.chunks_mut(width).collect(), tile, cond, lt);
+        }
+        return;
+    } else if data.len() == run_len {
+        let rows = data.chunks_mut(width).collect();
+        parallel_sweep_columns(rows, tile, cond, lt, pool, threads);
         return;
     }
 
-    parallel_bitonic_pass(l_half, r_half, cond, pool, threads);
+    let (l_runs, r_runs) = data.split_at_mut(data.len() / 2);
     let l_threads = threads / 2;
     let r_threads = threads - l_threads;
 
     pool.scope(|s| {
-        s.spawn(|_| {
-            let (ll_quarter, lr_quarter) = l_half.split_at_mut(l_half.len() / 2);
-            parallel_bitonic_merge(ll_quarter, lr_quarter, cond, pool, l_threads)
-        });
-        s.spawn(|_| {
-            let (rl_quarter, rr_quarter) = r_half.split_at_mut(r_half.len() / 2);
-            parallel_bitonic_merge(rl_quarter, rr_quarter, cond, pool, r_threads)
-        });
+        s.spawn(|_| parallel_merge_sweep(l_runs, run_len, width, tile, cond, lt, pool, l_threads));
+        s.spawn(|_| parallel_merge_sweep(r_runs, run_len, width, tile, cond, lt, pool, r_threads));
     });
 }
 
-fn parallel_bitonic_pass<T: Ord + Send>(
+fn parallel_sweep_columns<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    rows: Vec<&mut [T]>,
+    tile: usize,
+    cond: bool,
+    lt: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let width = rows[0].len();
+    if threads <= 1 || width <= tile {
+        sweep_columns(rows, tile, cond, lt);
+        return;
+    }
+
+    let (l_rows, r_rows): (Vec<_>, Vec<_>) = rows
+        .into_iter()
+        .map(|row| row.split_at_mut(width / 2))
+        .unzip();
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+
+    pool.scope(|s| {
+        s.spawn(|_| parallel_sweep_columns(l_rows, tile, cond, lt, pool, l_threads));
+        s.spawn(|_| parallel_sweep_columns(r_rows, tile, cond, lt, pool, r_threads));
+    });
+}
+
+// Runs the merge levels between rows, from rows.len() / 2 rows apart down to
+// adjacent rows, one tile of columns at a time.
+fn sweep_columns<T, F: Fn(&T, &T) -> Condition>(
+    mut rows: Vec<&mut [T]>,
+    tile: usize,
+    cond: bool,
+    lt: &F,
+) {
+    let width = rows[0].len();
+    for start in (0..width).step_by(tile) {
+        let end = (start + tile).min(width);
+
+        let mut apart = rows.len() / 2;
+        while apart >= 1 {
+            for r in (0..rows.len()).filter(|r| r % (2 * apart) < apart) {
+                let (l_rows, r_rows) = rows.split_at_mut(r + apart);
+                bitonic_pass(
+                    &mut l_rows[r][start..end],
+                    &mut r_rows[0][start..end],
+                    cond,
+                    lt,
+                );
+            }
+            apart /= 2;
+        }
+    }
+}
+
+// Merges each run of run_len elements, where a run fits in a block.
+fn parallel_merge_blocks<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    data: &mut [T],
+    run_len: usize,
+    cond: bool,
+    lt: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    if threads <= 1 || data.len() <= run_len {
+        for run in data.chunks_mut(run_len) {
+            let (l_half, r_half) = run.split_at_mut(run.len() / 2);
+            bitonic_merge(l_half, r_half, cond, lt);
+        }
+        return;
+    }
+
+    let (l_runs, r_runs) = data.split_at_mut(data.len() / 2);
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+
+    pool.scope(|s| {
+        s.spawn(|_| parallel_merge_blocks(l_runs, run_len, cond, lt, pool, l_threads));
+        s.spawn(|_| parallel_merge_blocks(r_runs, run_len, cond, lt, pool, r_threads));
+    });
+}
+
+fn parallel_bitonic_pass<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
     l_half: &mut [T],
     r_half: &mut [T],
     cond: bool,
+    lt: &F,
     pool: &ThreadPool,
     threads: usize,
 ) {
     if threads <= 1 {
-        bitonic_pass(l_half, r_half, cond);
+        bitonic_pass(l_half, r_half, cond, lt);
         return;
     }
 
-    // (0..l_half.len()).into_par_iter().for_each(|i| {
-    //     ops::swap(
-    //         (l_half[i] < r_half[i]) ^ cond,
-    //         &mut l_half[i],
-    //         &mut r_half[i],
-    //     );
-    // });
-
     let l_threads = threads / 2;
     let r_threads = threads - l_threads;
 
     let (ll_quarter, lr_quarter) = l_half.split_at_mut(l_half.len() / 2);
     let (rl_quarter, rr_quarter) = r_half.split_at_mut(r_half.len() / 2);
     pool.scope(|s| {
-        s.spawn(|_| parallel_bitonic_pass(ll_quarter, rl_quarter, cond, pool, l_threads));
-        s.spawn(|_| parallel_bitonic_pass(lr_quarter, rr_quarter, cond, pool, r_threads));
+        s.spawn(|_| parallel_bitonic_pass(ll_quarter, rl_quarter, cond, lt, pool, l_threads));
+        s.spawn(|_| parallel_bitonic_pass(lr_quarter, rr_quarter, cond, lt, pool, r_threads));
     });
 }
 
-pub fn bitonic_sort<T: Ord>(list: &mut [T], cond: bool) {
-    if list.len() <= 1 {
+// Bitonic sort for any length, without padding. The first half is sorted in the
+// opposite direction to the second so that together they form a bitonic
+// sequence. Merging a bitonic sequence of length n compares each of the first
+// n - m elements with the element m after it, where m is the largest power of
+// two below n, and then merges both sides. The network only depends on n.
+// Power of two lengths use the plain bitonic network.
+pub fn parallel_arbitrary_bitonic_sort<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    data: &mut [T],
+    cond: bool,
+    lt: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    if threads <= 1 || data.len() <= 1 {
+        arbitrary_bitonic_sort(data, cond, lt);
+        return;
+    } else if data.len().is_power_of_two() {
+        parallel_bitonic_sort(data, cond, lt, pool, threads);
+        return;
+    }
+
+    let (l_half, r_half) = data.split_at_mut(data.len() / 2);
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+
+    pool.scope(|s| {
+        s.spawn(|_| parallel_arbitrary_bitonic_sort(l_half, !cond, lt, pool, l_threads));
+        s.spawn(|_| parallel_arbitrary_bitonic_sort(r_half, cond, lt, pool, r_threads));
+    });
+    parallel_arbitrary_bitonic_merge(data, cond, lt, pool, threads);
+}
+
+fn parallel_arbitrary_bitonic_merge<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    data: &mut [T],
+    cond: bool,
+    lt: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let n = data.len();
+    if threads <= 1 || n <= 1 {
+        arbitrary_bitonic_merge(data, cond, lt);
+        return;
+    } else if n.is_power_of_two() {
+        parallel_bitonic_merge(data, cond, lt, pool, threads);
         return;
     }
 
-    let (l_half, r_half) = list.split_at_mut(list.len() / 2);
-    bitonic_sort(l_half, cond);
-    bitonic_sort(r_half, !cond);
-    bitonic_merge(l_half, r_half, cond);
+    let m = 1 << usize::ilog2(n - 1);
+    let (l_data, r_data) = data.split_at_mut(m);
+    parallel_bitonic_pass(&mut l_data[..(n - m)], r_data, cond, lt, pool, threads);
+
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+    pool.scope(|s| {
+        s.spawn(|_| parallel_arbitrary_bitonic_merge(l_data, cond, lt, pool, l_threads));
+        s.spawn(|_| parallel_arbitrary_bitonic_merge(r_data, cond, lt, pool, r_threads));
+    });
 }
 
-fn bitonic_merge<T: Ord>(l_half: &mut [T], r_half: &mut [T], cond: bool) {
-    if l_half.len() < 1 {
+pub fn arbitrary_bitonic_sort<T, F: Fn(&T, &T) -> Condition>(data: &mut [T], cond: bool, lt: &F) {
+    if data.len() <= 1 {
+        return;
+    } else if data.len().is_power_of_two() {
+        bitonic_sort(data, cond, lt);
         return;
     }
 
-    bitonic_pass(l_half, r_half, cond);
+    let (l_half, r_half) = data.split_at_mut(data.len() / 2);
+    arbitrary_bitonic_sort(l_half, !cond, lt);
+    arbitrary_bitonic_sort(r_half, cond, lt);
+    arbitrary_bitonic_merge(data, cond, lt);
+}
 
-    let (ll_quarter, lr_quarter) = l_half.split_at_mut(l_half.len() / 2);
-    let (rl_quarter, rr_quarter) = r_half.split_at_mut(r_half.len() / 2);
-    bitonic_merge(ll_quarter, lr_quarter, cond);
-    bitonic_merge(rl_quarter, rr_quarter, cond);
+// Once a part's length is a power of two, the rest of its merge is the blocked
+// one, rather than a recursion down to single elements.
+pub fn arbitrary_bitonic_merge<T, F: Fn(&T, &T) -> Condition>(data: &mut [T], cond: bool, lt: &F) {
+    let n = data.len();
+    if n <= 1 {
+        return;
+    } else if n.is_power_of_two() {
+        merge_blocked(data, cond, lt);
+        return;
+    }
+
+    let m = 1 << usize::ilog2(n - 1);
+    let (l_data, r_data) = data.split_at_mut(m);
+    bitonic_pass(&mut l_data[..(n - m)], r_data, cond, lt);
+    arbitrary_bitonic_merge(l_data, cond, lt);
+    arbitrary_bitonic_merge(r_data, cond, lt);
 }
 
+// Iterative bitonic sort for power of two lengths. Leaf blocks of LEAF elements
+// are sorted with unrolled networks, and each later stage merges runs of twice
+// the size in a loop, so the stack depth and number of calls no longer grow
+// with n. Run j of a stage is sorted in the direction it would get in the
+// recursive network, which flips once for every right half on the way down,
+// i.e. cond unless j has an odd number of ones. The comparators only depend on
+// n.
+const LEAF: usize = 8;
+
+pub fn bitonic_sort<T, F: Fn(&T, &T) -> Condition>(list: &mut [T], cond: bool, lt: &F) {
+    let n = list.len();
+    if n <= 1 {
+        return;
+    }
+
+    let direction = |j: usize| cond ^ (j.count_ones() % 2 == 1);
+    let leaf = LEAF.min(n);
+    let block = block_len::<T>().clamp(leaf, n);
+
+    // Stages up to the block size run one block at a time, so each block is
+    // sorted while it is in cache.
+    for (c, chunk) in list.chunks_mut(block).enumerate() {
+        for (j, run) in chunk.chunks_mut(leaf).enumerate() {
+            sort_leaf(run, direction(c * (block / leaf) + j), lt);
+        }
+
+        let mut k = 2 * leaf;
+        while k <= block {
+            for (j, run) in chunk.chunks_mut(k).enumerate() {
+                merge_blocked(run, direction(c * (block / k) + j), lt);
+            }
+            k *= 2;
+        }
+    }
+
+    let mut k = 2 * block;
+    while k <= n {
+        for (j, run) in list.chunks_mut(k).enumerate() {
+            merge_blocked(run, direction(j), lt);
+        }
+        k *= 2;
+    }
+}
+
+pub fn bitonic_merge<T, F: Fn(&T, &T) -> Condition>(
+    l_half: &mut [T],
+    r_half: &mut [T],
+    cond: bool,
+    lt: &F,
+) {
+    if l_half.is_empty() {
+        return;
+    }
+
+    bitonic_pass(l_half, r_half, cond, lt);
+    merge_blocked(l_half, cond, lt);
+    merge_blocked(r_half, cond, lt);
+}
+
+// Merges a bitonic list whose length is a power of two. Levels with strides that
+// span more than a block are whole passes. The rest are done block by block, so
+// each block stays in cache, and the last levels of each leaf are unrolled.
+fn merge_blocked<T, F: Fn(&T, &T) -> Condition>(data: &mut [T], cond: bool, lt: &F) {
+    let n = data.len();
+    if n <= 1 {
+        return;
+    }
+
+    let block = block_len::<T>().min(n);
+    let mut stride = n / 2;
+    while stride >= block {
+        for run in data.chunks_mut(2 * stride) {
+            let (l_half, r_half) = run.split_at_mut(stride);
+            bitonic_pass(l_half, r_half, cond, lt);
+        }
+        stride /= 2;
+    }
+
+    for chunk in data.chunks_mut(block) {
+        let mut s = stride;
+        while s >= LEAF {
+            for run in chunk.chunks_mut(2 * s) {
+                let (l_half, r_half) = run.split_at_mut(s);
+                bitonic_pass(l_half, r_half, cond, lt);
+            }
+            s /= 2;
+        }
+
+        for leaf in chunk.chunks_mut(LEAF.min(2 * stride)) {
+            merge_leaf(leaf, cond, lt);
+        }
+    }
+}
+
+#[inline(always)]
+fn compare_exchange<T, F: Fn(&T, &T) -> Condition>(
+    data: &mut [T],
+    i: usize,
+    j: usize,
+    cond: Condition,
+    lt: &F,
+) {
+    let (l, r) = data.split_at_mut(j);
+    ops::swap(lt(&l[i], &r[0]) ^ cond, &mut l[i], &mut r[0]);
+}
+
+macro_rules! network {
+    ($data: expr, $cond: expr, $lt: expr; $(($i: literal, $j: literal)),* $(,)?) => {
+        {
+            $(compare_exchange($data, $i, $j, $cond, $lt);)*
+        }
+    };
+}
+
+// Sorts 2, 4 or 8 elements with optimal networks (1, 5 and 19 comparators).
 #[inline]
-fn bitonic_pass<T: Ord>(l_half: &mut [T], r_half: &mut [T], cond: bool) {
+fn sort_leaf<T, F: Fn(&T, &T) -> Condition>(leaf: &mut [T], cond: bool, lt: &F) {
+    let cond = Condition::from(cond);
+    match leaf.len() {
+        2 => network!(leaf, cond, lt; (0, 1)),
+        4 => network!(leaf, cond, lt; (0, 1), (2, 3), (0, 2), (1, 3), (1, 2)),
+        8 => network!(leaf, cond, lt;
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 4), (1, 5), (2, 6), (3, 7),
+            (0, 1), (2, 3), (4, 5), (6, 7),
+            (2, 4), (3, 5),
+            (1, 4), (3, 6),
+            (1, 2), (3, 4), (5, 6),
+        ),
+        _ => unreachable!(),
+    }
+}
+
+// The last levels of a bitonic merge, on 2, 4 or 8 elements.
+#[inline]
+fn merge_leaf<T, F: Fn(&T, &T) -> Condition>(leaf: &mut [T], cond: bool, lt: &F) {
+    let cond = Condition::from(cond);
+    match leaf.len() {
+        2 => network!(leaf, cond, lt; (0, 1)),
+        4 => network!(leaf, cond, lt; (0, 2), (1, 3), (0, 1), (2, 3)),
+        8 => network!(leaf, cond, lt;
+            (0, 4), (1, 5), (2, 6), (3, 7),
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 1), (2, 3), (4, 5), (6, 7),
+        ),
+        _ => unreachable!(),
+    }
+}
+
+#[inline]
+fn bitonic_pass<T, F: Fn(&T, &T) -> Condition>(
+    l_half: &mut [T],
+    r_half: &mut [T],
+    cond: bool,
+    lt: &F,
+) {
+    let cond = Condition::from(cond);
     for i in 0..l_half.len() {
         ops::swap(
-            (l_half[i] < r_half[i]) ^ cond,
+            lt(&l_half[i], &r_half[i]) ^ cond,
             &mut l_half[i],
             &mut r_half[i],
         );
@@ -123,22 +466,160 @@ fn bitonic_pass<T: Ord>(l_half: &mut [T], r_half: &mut [T], cond: bool) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ObliviousOrd;
 
     extern crate test;
     use rayon::slice::ParallelSliceMut;
     use test::Bencher;
 
+    #[test]
+    fn test_bitonic_sort() {
+        for p in 0..12 {
+            let n = 1 << p;
+            let v: Vec<i32> = (0..n).map(|i| (i * 37 + 11) % 101 - 50).collect();
+            let mut expected = v.clone();
+            expected.sort();
+
+            let mut a = v.clone();
+            bitonic_sort(&mut a, true, &i32::olt);
+            assert_eq!(a, expected);
+
+            let mut a = v.clone();
+            bitonic_sort(&mut a, false, &i32::olt);
+            expected.reverse();
+            assert_eq!(a, expected);
+        }
+
+        // 4 KiB elements, so merges span several blocks.
+        let lt = |a: &[u64; 512], b: &[u64; 512]| u64::olt(&a[0], &b[0]);
+        let mut v: Vec<[u64; 512]> = (0..1024).map(|i| [(i * 37) % 1024; 512]).collect();
+        bitonic_sort(&mut v, true, &lt);
+        assert!(v.windows(2).all(|w| w[0][0] <= w[1][0]));
+    }
+
+    #[test]
+    fn test_parallel_bitonic_sort() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        // Blocks are 64 elements of 4 KiB and 512 of 512 bytes, so the sweeps
+        // with one, three and four levels all run.
+        fn check<const N: usize>(n: u64, pool: &ThreadPool) {
+            let lt = |a: &[u64; N], b: &[u64; N]| u64::olt(&a[0], &b[0]);
+            for threads in [2, 3, 8] {
+                for cond in [true, false] {
+                    let mut v: Vec<[u64; N]> = (0..n).map(|i| [(i * 37) % n; N]).collect();
+                    parallel_bitonic_sort(&mut v, cond, &lt, pool, threads);
+                    let sorted = v.windows(2).all(|w| w[0][0] <= w[1][0]);
+                    let reversed = v.windows(2).all(|w| w[0][0] >= w[1][0]);
+                    assert!(if cond { sorted } else { reversed });
+                }
+            }
+        }
+
+        check::<512>(1024, &pool);
+        check::<512>(128, &pool);
+        check::<64>(4096, &pool);
+    }
+
+    #[test]
+    fn test_arbitrary_bitonic_merge() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        // The halves are sorted in opposite directions, as arbitrary_bitonic_sort
+        // leaves them, and split into power of two parts of several sizes.
+        for n in [2, 3, 5, 12, 100, 1000, 1536, 3000] {
+            let mut v: Vec<i32> = (0..n).map(|i| (i * 37 + 11) % 101 - 50).collect();
+            let (l_half, r_half) = v.split_at_mut(n as usize / 2);
+            l_half.sort_by(|a, b| b.cmp(a));
+            r_half.sort();
+            let mut expected = v.clone();
+            expected.sort();
+
+            let mut a = v.clone();
+            arbitrary_bitonic_merge(&mut a, true, &i32::olt);
+            assert_eq!(a, expected);
+
+            for threads in [1, 2, 3] {
+                let mut a = v.clone();
+                parallel_arbitrary_bitonic_merge(&mut a, true, &i32::olt, &pool, threads);
+                assert_eq!(a, expected);
+            }
+        }
+    }
+
+    fn bench_bitonic_sort_threads(b: &mut Bencher, size: i64, threads: usize) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+
+        let mut v: Vec<i64> = (0..size).rev().collect();
+
+        b.iter(|| parallel_bitonic_sort(&mut v[..], true, &i64::olt, &pool, threads));
+    }
+
+    // Scaling with the number of threads, against bench_bitonic_sort_1_thread.
     #[bench]
     fn bench_bitonic_sort(b: &mut Bencher) {
+        bench_bitonic_sort_threads(b, 1 << 20, 8);
+    }
+
+    #[bench]
+    fn bench_bitonic_sort_1_thread(b: &mut Bencher) {
+        bench_bitonic_sort_threads(b, 1 << 20, 1);
+    }
+
+    #[bench]
+    fn bench_bitonic_sort_16_threads(b: &mut Bencher) {
+        bench_bitonic_sort_threads(b, 1 << 20, 16);
+    }
+
+    // Sizes well past the last level cache (128 and 512 MiB), where the cache
+    // blocking of the merges matters most. They take minutes in the unoptimized
+    // test build, so they are ignored there and run with
+    // `cargo bench -- --ignored bench_bitonic_sort_2`.
+    #[bench]
+    #[ignore]
+    fn bench_bitonic_sort_2_24_8_threads(b: &mut Bencher) {
+        bench_bitonic_sort_threads(b, 1 << 24, 8);
+    }
+
+    #[bench]
+    #[ignore]
+    fn bench_bitonic_sort_2_24_16_threads(b: &mut Bencher) {
+        bench_bitonic_sort_threads(b, 1 << 24, 16);
+    }
+
+    #[bench]
+    #[ignore]
+    fn bench_bitonic_sort_2_26_8_threads(b: &mut Bencher) {
+        bench_bitonic_sort_threads(b, 1 << 26, 8);
+    }
+
+    #[bench]
+    #[ignore]
+    fn bench_bitonic_sort_2_26_16_threads(b: &mut Bencher) {
+        bench_bitonic_sort_threads(b, 1 << 26, 16);
+    }
+
+    // A single merge, where the sweeps dominate.
+    #[bench]
+    fn bench_bitonic_merge(b: &mut Bencher) {
         let pool = rayon::ThreadPoolBuilder::new()
             .num_threads(8)
             .build()
             .unwrap();
 
         let size = 0x100000;
-        let mut v: Vec<i64> = (0..size).rev().collect();
+        let mut v: Vec<i64> = (0..size).chain((0..size).rev()).collect();
 
-        b.iter(|| parallel_bitonic_sort(&mut v[..], true, &pool, 8));
+        b.iter(|| parallel_bitonic_merge(&mut v[..], true, &i64::olt, &pool, 8));
     }
 
     struct BigElem {
@@ -182,9 +663,10 @@ mod tests {
             .build()
             .unwrap();
         let size = 0x100000;
-        let mut v: Vec<BigElem> = (0..size).rev().map(|i| BigElem::new(i)).collect();
+        let mut v: Vec<BigElem> = (0..size).rev().map(BigElem::new).collect();
+        let lt = |a: &BigElem, b: &BigElem| u64::olt(&a.key, &b.key);
 
-        b.iter(|| parallel_bitonic_sort(&mut v[..], true, &pool, 8));
+        b.iter(|| parallel_bitonic_sort(&mut v[..], true, &lt, &pool, 8));
     }
 
     #[bench]
@@ -194,7 +676,7 @@ mod tests {
             .build()
             .unwrap();
         let size = 0x100000;
-        let mut v: Vec<BigElem> = (0..size).rev().map(|i| BigElem::new(i)).collect();
+        let mut v: Vec<BigElem> = (0..size).rev().map(BigElem::new).collect();
 
         b.iter(|| {
             pool.install(|| {
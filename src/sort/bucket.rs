@@ -0,0 +1,385 @@
+use super::{bitonic, stable_lt};
+use crate::compact;
+use crate::{Condition, ObliviousOps, ObliviousOrd};
+use rand::rngs::OsRng;
+use rand::TryRngCore;
+use rayon::slice::ParallelSliceMut;
+use rayon::ThreadPool;
+use std::cmp::Ordering;
+use std::mem::MaybeUninit;
+
+// Bucket oblivious sort (Asharov, Chan, Nayak, Pass, Ren and Shi, SOSA 2020).
+//
+// Elements get uniformly random bucket labels and are spread over B buckets of
+// Z slots, at most half full and padded with dummies. Each of the log B levels
+// of a butterfly network merges pairs of buckets and splits them by one bit of
+// the label, using a bitonic network on the 2Z slots, so afterwards every
+// element sits in the bucket named by its label. Shuffling each bucket and
+// compacting away the dummies gives a uniformly random permutation of the input,
+// which can then be sorted with an ordinary comparison sort: the comparison
+// results only depend on that permutation. Ties are broken by original index so
+// that keys are distinct, which also makes the sort stable.
+//
+// This is O(n log n) work for a fixed Z, but every level merges and splits with
+// a bitonic network on 2Z slots, so the constant is large. It has not been
+// measured to beat the bitonic sort at any size.
+//
+// Each bucket starts with at most Z/2 real elements, so at every level a bucket
+// receives Z/2 of them in expectation, and by a Chernoff bound overflows with
+// probability at most e^(-Z/6), about 2^-123 for Z = 512. A union bound over
+// the B log B buckets of all levels keeps the chance of any overflow below
+// 2^-90 for n up to 2^32. On overflow the placement is retried with fresh
+// labels; only the fact that a retry happened is revealed.
+
+// Z, the number of slots in a bucket.
+const BUCKET_SIZE: usize = 512;
+
+struct Slot<T> {
+    elem: MaybeUninit<(T, usize)>,
+    label: usize,
+    dummy: Condition,
+    key: u64,
+}
+
+impl<T> Slot<T> {
+    fn real(elem: (T, usize), label: usize) -> Self {
+        Slot {
+            elem: MaybeUninit::new(elem),
+            label,
+            dummy: Condition::FALSE,
+            key: 0,
+        }
+    }
+
+    fn dummy() -> Self {
+        // Zeroed rather than uninitialized since swaps read every byte.
+        Slot {
+            elem: MaybeUninit::zeroed(),
+            label: 0,
+            dummy: Condition::TRUE,
+            key: 0,
+        }
+    }
+}
+
+fn lt_key<T>(a: &Slot<T>, b: &Slot<T>) -> Condition {
+    u64::olt(&a.key, &b.key)
+}
+
+pub fn parallel_bucket_sort<T: ObliviousOrd + Send>(
+    list: Vec<T>,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Vec<T> {
+    if threads <= 1 {
+        return bucket_sort(list);
+    }
+
+    let n = list.len();
+    let buckets = num_buckets(n);
+    let mut items: Vec<(T, usize)> = list.into_iter().zip(0..).collect();
+
+    loop {
+        let mut slots = distribute(items, buckets);
+        let overflow = parallel_butterfly(&mut slots, buckets, pool, threads);
+        let placed = !bool::from(overflow);
+        if placed {
+            parallel_shuffle_buckets(&mut slots, pool, threads);
+        }
+
+        let bits: Vec<Condition> = slots.iter().map(|s| !s.dummy).collect();
+        compact::par_compact(&mut slots, &bits, pool, threads);
+        items = take_reals(slots, n);
+        if placed {
+            break;
+        }
+    }
+
+    pool.install(|| items.par_sort_unstable_by(compare));
+    items.into_iter().map(|(t, _)| t).collect()
+}
+
+fn parallel_butterfly<T: Send>(
+    slots: &mut [Slot<T>],
+    buckets: usize,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Condition {
+    let mut overflow = Condition::FALSE;
+    for level in 0..buckets.ilog2() {
+        overflow |= parallel_butterfly_level(slots, level, pool, threads);
+    }
+    overflow
+}
+
+// Buckets j and j + 2^level of each group of 2^(level + 1) buckets are merged and
+// split. Groups are split across threads first, and then the pairs in a group.
+fn parallel_butterfly_level<T: Send>(
+    groups: &mut [Slot<T>],
+    level: u32,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Condition {
+    if threads <= 1 {
+        return butterfly_level(groups, level);
+    }
+
+    let half = (1 << level) * BUCKET_SIZE;
+    if groups.len() == 2 * half {
+        let (l_half, r_half) = groups.split_at_mut(half);
+        return parallel_merge_split_pairs(l_half, r_half, level, pool, threads);
+    }
+
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+    let (l_groups, r_groups) = groups.split_at_mut(groups.len() / 2);
+    let (mut l_overflow, mut r_overflow) = (Condition::FALSE, Condition::FALSE);
+    pool.scope(|s| {
+        s.spawn(|_| l_overflow = parallel_butterfly_level(l_groups, level, pool, l_threads));
+        s.spawn(|_| r_overflow = parallel_butterfly_level(r_groups, level, pool, r_threads));
+    });
+    l_overflow | r_overflow
+}
+
+fn parallel_merge_split_pairs<T: Send>(
+    l_half: &mut [Slot<T>],
+    r_half: &mut [Slot<T>],
+    level: u32,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Condition {
+    if threads <= 1 || l_half.len() <= BUCKET_SIZE {
+        return merge_split_pairs(l_half, r_half, level);
+    }
+
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+    let (ll_quarter, lr_quarter) = l_half.split_at_mut(l_half.len() / 2);
+    let (rl_quarter, rr_quarter) = r_half.split_at_mut(r_half.len() / 2);
+    let (mut l_overflow, mut r_overflow) = (Condition::FALSE, Condition::FALSE);
+    pool.scope(|s| {
+        s.spawn(|_| {
+            l_overflow = parallel_merge_split_pairs(ll_quarter, rl_quarter, level, pool, l_threads)
+        });
+        s.spawn(|_| {
+            r_overflow = parallel_merge_split_pairs(lr_quarter, rr_quarter, level, pool, r_threads)
+        });
+    });
+    l_overflow | r_overflow
+}
+
+fn parallel_shuffle_buckets<T: Send>(slots: &mut [Slot<T>], pool: &ThreadPool, threads: usize) {
+    if threads <= 1 || slots.len() <= BUCKET_SIZE {
+        shuffle_buckets(slots);
+        return;
+    }
+
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+    let (l_slots, r_slots) = slots.split_at_mut(slots.len() / 2);
+    pool.scope(|s| {
+        s.spawn(|_| parallel_shuffle_buckets(l_slots, pool, l_threads));
+        s.spawn(|_| parallel_shuffle_buckets(r_slots, pool, r_threads));
+    });
+}
+
+pub fn bucket_sort<T: ObliviousOrd>(list: Vec<T>) -> Vec<T> {
+    let n = list.len();
+    let buckets = num_buckets(n);
+    let mut items: Vec<(T, usize)> = list.into_iter().zip(0..).collect();
+
+    loop {
+        let mut slots = distribute(items, buckets);
+        let overflow = butterfly(&mut slots, buckets);
+        let placed = !bool::from(overflow);
+        if placed {
+            shuffle_buckets(&mut slots);
+        }
+
+        let bits: Vec<Condition> = slots.iter().map(|s| !s.dummy).collect();
+        compact::compact(&mut slots, &bits);
+        items = take_reals(slots, n);
+        if placed {
+            break;
+        }
+    }
+
+    items.sort_unstable_by(compare);
+    items.into_iter().map(|(t, _)| t).collect()
+}
+
+// The smallest power of two number of buckets that are at most half full.
+fn num_buckets(n: usize) -> usize {
+    (2 * n).div_ceil(BUCKET_SIZE).next_power_of_two()
+}
+
+// Fills each bucket with the next n / B elements, in order, and pads it with
+// dummies. Only the labels are random.
+fn distribute<T>(items: Vec<(T, usize)>, buckets: usize) -> Vec<Slot<T>> {
+    let per_bucket = items.len().div_ceil(buckets);
+    let mut items = items.into_iter();
+
+    let mut slots = Vec::with_capacity(buckets * BUCKET_SIZE);
+    for _ in 0..buckets {
+        let start = slots.len();
+        for elem in items.by_ref().take(per_bucket) {
+            let label = OsRng.try_next_u64().unwrap() as usize & (buckets - 1);
+            slots.push(Slot::real(elem, label));
+        }
+        slots.resize_with(start + BUCKET_SIZE, Slot::dummy);
+    }
+    slots
+}
+
+fn butterfly<T>(slots: &mut [Slot<T>], buckets: usize) -> Condition {
+    let mut overflow = Condition::FALSE;
+    for level in 0..buckets.ilog2() {
+        overflow |= butterfly_level(slots, level);
+    }
+    overflow
+}
+
+fn butterfly_level<T>(slots: &mut [Slot<T>], level: u32) -> Condition {
+    let half = (1 << level) * BUCKET_SIZE;
+    let mut overflow = Condition::FALSE;
+    for group in slots.chunks_mut(2 * half) {
+        let (l_half, r_half) = group.split_at_mut(half);
+        overflow |= merge_split_pairs(l_half, r_half, level);
+    }
+    overflow
+}
+
+fn merge_split_pairs<T>(l_half: &mut [Slot<T>], r_half: &mut [Slot<T>], level: u32) -> Condition {
+    let mut overflow = Condition::FALSE;
+    for (l_bucket, r_bucket) in l_half
+        .chunks_mut(BUCKET_SIZE)
+        .zip(r_half.chunks_mut(BUCKET_SIZE))
+    {
+        overflow |= merge_split(l_bucket, r_bucket, level);
+    }
+    overflow
+}
+
+// Moves the real elements of both buckets whose label has bit `level` unset into
+// l_bucket, and the rest into r_bucket. Dummies are routed so that each side
+// gets exactly Z slots, which is impossible if more than Z real elements go to
+// one side; that is reported as an overflow.
+fn merge_split<T>(l_bucket: &mut [Slot<T>], r_bucket: &mut [Slot<T>], level: u32) -> Condition {
+    let z = l_bucket.len();
+    let bit = |s: &Slot<T>| Condition::from(((s.label >> level) & 1) as u8);
+
+    let (mut zeros, mut ones) = (0, 0);
+    for s in l_bucket.iter().chain(r_bucket.iter()) {
+        zeros += (!s.dummy & !bit(s)).as_u8() as usize;
+        ones += (!s.dummy & bit(s)).as_u8() as usize;
+    }
+    let l_overflow = usize::olt(&z, &zeros);
+    let overflow = l_overflow | usize::olt(&z, &ones);
+
+    // Sorting by (side, dummy) keeps the real elements of each side in front.
+    let mut l_dummies = usize::oselect(l_overflow, 0, z.wrapping_sub(zeros));
+    for s in l_bucket.iter_mut().chain(r_bucket.iter_mut()) {
+        let dummy_left = s.dummy & !usize::oequal(&l_dummies, &0);
+        l_dummies -= dummy_left.as_u8() as usize;
+
+        let side = Condition::oselect(s.dummy, !dummy_left, bit(s));
+        s.key = ((side.as_u8() as u64) << 1) | s.dummy.as_u8() as u64;
+    }
+
+    bitonic::bitonic_sort(l_bucket, true, &lt_key);
+    bitonic::bitonic_sort(r_bucket, false, &lt_key);
+    bitonic::bitonic_merge(l_bucket, r_bucket, true, &lt_key);
+    overflow
+}
+
+// Puts the real elements of each bucket first, in a random order.
+fn shuffle_buckets<T>(slots: &mut [Slot<T>]) {
+    for bucket in slots.chunks_mut(BUCKET_SIZE) {
+        for s in bucket.iter_mut() {
+            let r = OsRng.try_next_u64().unwrap() >> 1;
+            s.key = ((s.dummy.as_u8() as u64) << 63) | r;
+        }
+        bitonic::bitonic_sort(bucket, true, &lt_key);
+    }
+}
+
+// Takes the elements out of the first n slots, which hold the real elements
+// after compaction.
+fn take_reals<T>(slots: Vec<Slot<T>>, n: usize) -> Vec<(T, usize)> {
+    slots
+        .into_iter()
+        .take(n)
+        // SAFETY: Compaction moved all n real slots, and only those, to the front.
+        .map(|s| unsafe { s.elem.assume_init() })
+        .collect()
+}
+
+// SECURITY: Reveals the result of each comparison. The elements are distinct and
+// in a uniformly random order by now, so the results do not depend on the input.
+fn compare<T: ObliviousOrd>(a: &(T, usize), b: &(T, usize)) -> Ordering {
+    if bool::from(stable_lt(a, b)) {
+        Ordering::Less
+    } else if bool::from(stable_lt(b, a)) {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate test;
+    use test::Bencher;
+
+    #[test]
+    fn test_bucket_sort() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        for n in [0, 1, 2, 5, 100, 1000, 3000] {
+            let data: Vec<i32> = (0..n).map(|i| (i * 37 + 11) % 101 - 50).collect();
+            let mut expected = data.clone();
+            expected.sort();
+
+            assert_eq!(bucket_sort(data.clone()), expected);
+            for threads in [2, 3] {
+                assert_eq!(parallel_bucket_sort(data.clone(), &pool, threads), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_split() {
+        let mut l: Vec<Slot<u32>> = (0..BUCKET_SIZE).map(|_| Slot::dummy()).collect();
+        let mut r: Vec<Slot<u32>> = (0..BUCKET_SIZE).map(|_| Slot::dummy()).collect();
+        for i in 0..(BUCKET_SIZE / 2) {
+            l[i] = Slot::real((i as u32, i), i % 4);
+            r[i] = Slot::real((i as u32, i), (i + 1) % 4);
+        }
+
+        let overflow = merge_split(&mut l, &mut r, 1);
+        assert!(!bool::from(overflow));
+        for (bucket, side) in [(&l, 0), (&r, 1)] {
+            let reals: Vec<&Slot<u32>> = bucket.iter().filter(|s| !bool::from(s.dummy)).collect();
+            assert_eq!(reals.len(), BUCKET_SIZE / 2);
+            assert!(reals.iter().all(|s| (s.label >> 1) & 1 == side));
+        }
+    }
+
+    #[bench]
+    fn bench_bucket_sort(b: &mut Bencher) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+
+        let size = 0x10000;
+        let v: Vec<i64> = (0..size).rev().collect();
+
+        b.iter(|| parallel_bucket_sort(v.clone(), &pool, 8));
+    }
+}
@@ -0,0 +1,348 @@
+mod store;
+pub use store::{BackingStore, FileStore, MemoryStore};
+
+use super::{bitonic, odd_even_merge, SortAlgorithm};
+use crate::{Condition, ObliviousOrd};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::rngs::OsRng;
+use rand::TryRngCore;
+use std::io;
+use std::marker::PhantomData;
+
+// Oblivious sorting for data that does not fit in protected memory.
+//
+// An ExternalVec keeps its elements in a BackingStore, in blocks of block_len
+// slots. Each slot holds a dummy flag and an encoded element, and the last block
+// is padded with dummies. Blocks are encrypted with ChaCha20-Poly1305 under a
+// key that never leaves memory. The nonce of a write is a counter, and the
+// counter each block was last written with is kept in memory, so the store can
+// neither read nor modify blocks, nor swap them or replay old ones.
+//
+// Sorting first sorts each block in memory, and then runs Batcher's odd-even
+// merge sort over the blocks, with a merge-split of two sorted blocks in place of
+// each compare-exchange. Any sorting network stays correct with merge-splits, so
+// the blocks end up sorted. Only two blocks are in memory at once, and the blocks
+// read and written only depend on the number of blocks, i.e. on n.
+
+// Fixed size encoding of elements stored in an ExternalVec. read_bytes must
+// accept all zero bytes, which are used for padding.
+pub trait Record: Sized {
+    const SIZE: usize;
+
+    fn write_bytes(&self, out: &mut [u8]);
+    fn read_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_record {
+    ($($t: ty),*) => {
+        $(
+            impl Record for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn write_bytes(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_bytes(bytes: &[u8]) -> Self {
+                    <$t>::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_record!(i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize);
+
+impl<T: Record, const N: usize> Record for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+
+    fn write_bytes(&self, out: &mut [u8]) {
+        for (t, out) in self.iter().zip(out.chunks_exact_mut(T::SIZE)) {
+            t.write_bytes(out);
+        }
+    }
+
+    fn read_bytes(bytes: &[u8]) -> Self {
+        std::array::from_fn(|i| T::read_bytes(&bytes[(i * T::SIZE)..((i + 1) * T::SIZE)]))
+    }
+}
+
+const TAG_SIZE: usize = 16;
+
+// A (dummy, element) pair.
+type Slot<T> = (Condition, T);
+
+pub struct ExternalVec<T, S> {
+    store: S,
+    cipher: ChaCha20Poly1305,
+    len: usize,
+    block_len: usize,
+    versions: Vec<u64>,
+    writes: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Record, S: BackingStore> ExternalVec<T, S> {
+    // Encrypts the elements of iter into store, block_len elements per block.
+    pub fn new<I: IntoIterator<Item = T>>(store: S, block_len: usize, iter: I) -> io::Result<Self> {
+        assert!(block_len > 0);
+
+        let mut key = [0; 32];
+        OsRng.try_fill_bytes(&mut key).unwrap();
+        let mut vec = ExternalVec {
+            store,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            len: 0,
+            block_len,
+            versions: Vec::new(),
+            writes: 0,
+            _marker: PhantomData,
+        };
+
+        let mut block = Vec::with_capacity(block_len);
+        for t in iter {
+            block.push((Condition::FALSE, t));
+            if block.len() == block_len {
+                vec.push_block(&mut block)?;
+            }
+        }
+        if !block.is_empty() {
+            vec.push_block(&mut block)?;
+        }
+        Ok(vec)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.versions.len()
+    }
+
+    // Decrypts block `index`, without its padding.
+    pub fn read_block(&mut self, index: usize) -> io::Result<Vec<T>> {
+        if index >= self.num_blocks() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "block index out of bounds",
+            ));
+        }
+        let reals = self.block_len.min(self.len - index * self.block_len);
+        let slots = self.load(index)?;
+        Ok(slots.into_iter().take(reals).map(|(_, t)| t).collect())
+    }
+
+    pub fn to_vec(&mut self) -> io::Result<Vec<T>> {
+        let mut out = Vec::with_capacity(self.len);
+        for index in 0..self.num_blocks() {
+            out.extend(self.read_block(index)?);
+        }
+        Ok(out)
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    fn push_block(&mut self, block: &mut Vec<Slot<T>>) -> io::Result<()> {
+        self.len += block.len();
+        block.resize_with(self.block_len, || {
+            (Condition::TRUE, T::read_bytes(&vec![0; T::SIZE]))
+        });
+        self.save(self.versions.len(), block)?;
+        block.clear();
+        Ok(())
+    }
+
+    fn load(&mut self, index: usize) -> io::Result<Vec<Slot<T>>> {
+        let slot_size = 1 + T::SIZE;
+        let mut buf = vec![0; self.block_len * slot_size + TAG_SIZE];
+        self.store.read_block(index, &mut buf)?;
+
+        let payload = Payload {
+            msg: &buf,
+            aad: &(index as u64).to_le_bytes(),
+        };
+        let plain = self
+            .cipher
+            .decrypt(&nonce(self.versions[index]), payload)
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "block failed authentication")
+            })?;
+
+        Ok(plain
+            .chunks_exact(slot_size)
+            .map(|slot| (Condition::from(slot[0]), T::read_bytes(&slot[1..])))
+            .collect())
+    }
+
+    fn save(&mut self, index: usize, slots: &[Slot<T>]) -> io::Result<()> {
+        let slot_size = 1 + T::SIZE;
+        let mut plain = vec![0; self.block_len * slot_size];
+        for (out, (dummy, t)) in plain.chunks_exact_mut(slot_size).zip(slots) {
+            out[0] = dummy.as_u8();
+            t.write_bytes(&mut out[1..]);
+        }
+
+        self.writes += 1;
+        let payload = Payload {
+            msg: &plain,
+            aad: &(index as u64).to_le_bytes(),
+        };
+        let cipher = self
+            .cipher
+            .encrypt(&nonce(self.writes), payload)
+            .expect("block too large to encrypt");
+        self.store.write_block(index, &cipher)?;
+
+        if index == self.versions.len() {
+            self.versions.push(self.writes);
+        } else {
+            self.versions[index] = self.writes;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Record + ObliviousOrd, S: BackingStore> ExternalVec<T, S> {
+    pub fn sort(&mut self) -> io::Result<()> {
+        let blocks = self.num_blocks();
+        for index in 0..blocks {
+            let mut slots = self.load(index)?;
+            SortAlgorithm::default().sort(&mut slots, true, &slot_lt);
+            self.save(index, &slots)?;
+        }
+
+        for (p, k) in odd_even_merge::stages(blocks) {
+            for (a, b) in odd_even_merge::comparators(blocks, p, k) {
+                self.merge_split(a, b)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Leaves the smaller half of two sorted blocks in block a and the larger half
+    // in block b.
+    fn merge_split(&mut self, a: usize, b: usize) -> io::Result<()> {
+        let mut slots = self.load(a)?;
+        slots.reverse();
+        slots.extend(self.load(b)?);
+        bitonic::arbitrary_bitonic_merge(&mut slots, true, &slot_lt);
+
+        let (low, high) = slots.split_at(self.block_len);
+        self.save(a, low)?;
+        self.save(b, high)
+    }
+}
+
+// Real elements in order, followed by the dummies.
+fn slot_lt<T: ObliviousOrd>(a: &Slot<T>, b: &Slot<T>) -> Condition {
+    !a.0 & (b.0 | T::olt(&a.1, &b.1))
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&counter.to_le_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records the blocks a store is asked for.
+    #[derive(Default)]
+    struct TracingStore {
+        inner: MemoryStore,
+        trace: Vec<(bool, usize)>,
+    }
+
+    impl BackingStore for TracingStore {
+        fn read_block(&mut self, index: usize, buf: &mut [u8]) -> io::Result<()> {
+            self.trace.push((false, index));
+            self.inner.read_block(index, buf)
+        }
+
+        fn write_block(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+            self.trace.push((true, index));
+            self.inner.write_block(index, data)
+        }
+    }
+
+    #[test]
+    fn test_external_sort() {
+        for n in [0, 1, 5, 8, 37, 100, 257] {
+            let data: Vec<i64> = (0..n).map(|i| (i * 37 + 11) % 101 - 50).collect();
+            let mut expected = data.clone();
+            expected.sort();
+
+            let mut v = ExternalVec::new(MemoryStore::new(), 8, data.clone()).unwrap();
+            assert_eq!(v.len(), data.len());
+            v.sort().unwrap();
+            assert_eq!(v.to_vec().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_read_block() {
+        let mut v = ExternalVec::new(MemoryStore::new(), 4, 0..10u32).unwrap();
+        assert_eq!(v.num_blocks(), 3);
+        assert_eq!(v.read_block(2).unwrap(), vec![8, 9]);
+        for index in [3, usize::MAX] {
+            let err = v.read_block(index).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_file_store() {
+        let data: Vec<[u32; 3]> = (0..100).map(|i| [(i * 37) % 101, i, 7]).collect();
+        let mut expected = data.clone();
+        expected.sort();
+
+        let store = FileStore::new(tempfile::tempfile().unwrap());
+        let mut v = ExternalVec::new(store, 16, data).unwrap();
+        v.sort().unwrap();
+        assert_eq!(v.to_vec().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_access_pattern() {
+        let trace = |data: Vec<u64>| {
+            let mut v = ExternalVec::new(TracingStore::default(), 4, data).unwrap();
+            v.sort().unwrap();
+            v.into_store().trace
+        };
+
+        let sorted = trace((0..50).collect());
+        let reversed = trace((0..50).rev().collect());
+        let constant = trace(vec![3; 50]);
+        assert_eq!(sorted, reversed);
+        assert_eq!(sorted, constant);
+    }
+
+    #[test]
+    fn test_tampering() {
+        let mut v = ExternalVec::new(MemoryStore::new(), 4, 0..20u32).unwrap();
+        let mut block = vec![0; 4 * (1 + u32::SIZE) + TAG_SIZE];
+
+        // A modified block.
+        v.store.read_block(1, &mut block).unwrap();
+        block[0] ^= 1;
+        v.store.write_block(1, &block).unwrap();
+        assert_eq!(v.sort().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // An old version of a block.
+        let mut v = ExternalVec::new(MemoryStore::new(), 4, (0..20u32).rev()).unwrap();
+        v.store.read_block(0, &mut block).unwrap();
+        v.sort().unwrap();
+        v.store.write_block(0, &block).unwrap();
+        assert_eq!(v.to_vec().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
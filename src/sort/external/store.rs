@@ -0,0 +1,71 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+// Untrusted storage for the encrypted blocks of an ExternalVec. All blocks of a
+// store have the same size. Stores see which block is read or written and when,
+// but never plaintext, and anything they return is authenticated.
+pub trait BackingStore {
+    fn read_block(&mut self, index: usize, buf: &mut [u8]) -> io::Result<()>;
+    fn write_block(&mut self, index: usize, data: &[u8]) -> io::Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    blocks: Vec<Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BackingStore for MemoryStore {
+    fn read_block(&mut self, index: usize, buf: &mut [u8]) -> io::Result<()> {
+        match self.blocks.get(index) {
+            Some(block) if block.len() == buf.len() => {
+                buf.copy_from_slice(block);
+                Ok(())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "block missing or of the wrong size",
+            )),
+        }
+    }
+
+    fn write_block(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+        if index >= self.blocks.len() {
+            self.blocks.resize_with(index + 1, Vec::new);
+        }
+        self.blocks[index].clear();
+        self.blocks[index].extend_from_slice(data);
+        Ok(())
+    }
+}
+
+// Keeps block i at offset i * block size of a file.
+#[derive(Debug)]
+pub struct FileStore {
+    file: File,
+}
+
+impl FileStore {
+    pub fn new(file: File) -> Self {
+        FileStore { file }
+    }
+}
+
+impl BackingStore for FileStore {
+    fn read_block(&mut self, index: usize, buf: &mut [u8]) -> io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start((index * buf.len()) as u64))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write_block(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start((index * data.len()) as u64))?;
+        self.file.write_all(data)
+    }
+}
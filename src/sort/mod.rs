@@ -1,28 +1,181 @@
 mod bitonic;
-use crate::Max;
+mod bucket;
+#[cfg(feature = "external")]
+mod external;
+mod odd_even_merge;
+mod order;
+use crate::ops::is_less;
+use crate::{Condition, ObliviousOps, ObliviousOrd};
 use rayon::ThreadPool;
 
-pub fn sort<T: Ord + Max>(mut list: Vec<T>) -> Vec<T> {
-    let list_len = list.len();
-    let remaining = list_len.next_power_of_two() - list_len;
-    list.extend((0..remaining).map(|_| T::maximum()));
+pub use order::SortOrder;
 
-    bitonic::bitonic_sort(&mut list, true);
-    list.truncate(list_len);
+#[cfg(feature = "external")]
+pub use external::{BackingStore, ExternalVec, FileStore, MemoryStore, Record};
+
+// The sorting network used by the `_with` functions. Bitonic sort is the
+// default. Batcher's odd-even merge sort has the same depth but uses fewer
+// compare-exchanges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortAlgorithm {
+    #[default]
+    Bitonic,
+    OddEvenMerge,
+}
+
+impl SortAlgorithm {
+    fn sort<T, F: Fn(&T, &T) -> Condition>(self, data: &mut [T], cond: bool, lt: &F) {
+        match self {
+            SortAlgorithm::Bitonic => bitonic::arbitrary_bitonic_sort(data, cond, lt),
+            SortAlgorithm::OddEvenMerge => odd_even_merge::odd_even_merge_sort(data, cond, lt),
+        }
+    }
+
+    fn par_sort<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+        self,
+        data: &mut [T],
+        cond: bool,
+        lt: &F,
+        pool: &ThreadPool,
+        threads: usize,
+    ) {
+        match self {
+            SortAlgorithm::Bitonic => {
+                bitonic::parallel_arbitrary_bitonic_sort(data, cond, lt, pool, threads)
+            }
+            SortAlgorithm::OddEvenMerge => {
+                odd_even_merge::parallel_odd_even_merge_sort(data, cond, lt, pool, threads)
+            }
+        }
+    }
+}
+
+pub fn sort<T: ObliviousOrd>(mut list: Vec<T>) -> Vec<T> {
+    sort_slice(&mut list);
     list
 }
 
-pub fn par_sort<T: Ord + Send + Max>(
+pub fn par_sort<T: ObliviousOrd + Send>(
     mut list: Vec<T>,
     pool: &ThreadPool,
     threads: usize,
 ) -> Vec<T> {
-    let list_len = list.len();
-    let remaining = list_len.next_power_of_two() - list_len;
-    list.extend((0..remaining).map(|_| T::maximum()));
+    par_sort_slice(&mut list, pool, threads);
+    list
+}
+
+// Sorts in place with a bitonic network for arbitrary lengths, so no padding
+// elements (or Max impl) are needed.
+pub fn sort_slice<T: ObliviousOrd>(data: &mut [T]) {
+    sort_slice_with(data, SortAlgorithm::default());
+}
+
+pub fn par_sort_slice<T: ObliviousOrd + Send>(data: &mut [T], pool: &ThreadPool, threads: usize) {
+    par_sort_slice_with(data, SortAlgorithm::default(), pool, threads);
+}
+
+pub fn sort_slice_with<T: ObliviousOrd>(data: &mut [T], algorithm: SortAlgorithm) {
+    algorithm.sort(data, true, &T::olt);
+}
+
+pub fn par_sort_slice_with<T: ObliviousOrd + Send>(
+    data: &mut [T],
+    algorithm: SortAlgorithm,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    algorithm.par_sort(data, true, &T::olt, pool, threads);
+}
+
+// Stable sorts. Each element is paired with its original index, and equal
+// elements are ordered by index, so the output order of equal elements does not
+// depend on the sorting network.
+pub fn stable_sort<T: ObliviousOrd>(list: Vec<T>) -> Vec<T> {
+    let mut indexed: Vec<(T, usize)> = list.into_iter().zip(0..).collect();
+    SortAlgorithm::default().sort(&mut indexed, true, &stable_lt);
+    indexed.into_iter().map(|(t, _)| t).collect()
+}
+
+pub fn par_stable_sort<T: ObliviousOrd + Send>(
+    list: Vec<T>,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Vec<T> {
+    let mut indexed: Vec<(T, usize)> = list.into_iter().zip(0..).collect();
+    SortAlgorithm::default().par_sort(&mut indexed, true, &stable_lt, pool, threads);
+    indexed.into_iter().map(|(t, _)| t).collect()
+}
+
+fn stable_lt<T: ObliviousOrd>(a: &(T, usize), b: &(T, usize)) -> Condition {
+    let tie = !T::olt(&b.0, &a.0) & usize::olt(&a.1, &b.1);
+    T::olt(&a.0, &b.0) | tie
+}
+
+// Bucket oblivious sort does O(n log n) work instead of the O(n log^2 n) of the
+// sorting networks, but with a constant large enough that it has not been
+// measured to be faster at any size. It is randomized and stable.
+pub fn bucket_sort<T: ObliviousOrd>(list: Vec<T>) -> Vec<T> {
+    bucket::bucket_sort(list)
+}
+
+pub fn par_bucket_sort<T: ObliviousOrd + Send>(
+    list: Vec<T>,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Vec<T> {
+    bucket::parallel_bucket_sort(list, pool, threads)
+}
+
+// Sorts by a key computed from each element, so records with large payloads can
+// be sorted by one field without a wrapper type. The key is recomputed for every
+// comparison.
+pub fn sort_by_key<T, K: ObliviousOps, F: Fn(&T) -> K>(data: &mut [T], key: F) {
+    let lt = |a: &T, b: &T| K::olt(&key(a), &key(b));
+    SortAlgorithm::default().sort(data, true, &lt);
+}
+
+pub fn par_sort_by_key<T: Send, K: ObliviousOps, F: Fn(&T) -> K + Sync>(
+    data: &mut [T],
+    key: F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let lt = |a: &T, b: &T| K::olt(&key(a), &key(b));
+    SortAlgorithm::default().par_sort(data, true, &lt, pool, threads);
+}
 
-    bitonic::parallel_bitonic_sort(&mut list, true, pool, threads);
-    list.truncate(list_len);
+// Sorts with a caller supplied comparator that behaves like ocompare: it returns
+// -1, 0 or 1 and must itself be oblivious.
+pub fn sort_by<T, F: Fn(&T, &T) -> i8>(data: &mut [T], compare: F) {
+    let lt = |a: &T, b: &T| is_less(compare(a, b));
+    SortAlgorithm::default().sort(data, true, &lt);
+}
+
+pub fn par_sort_by<T: Send, F: Fn(&T, &T) -> i8 + Sync>(
+    data: &mut [T],
+    compare: F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let lt = |a: &T, b: &T| is_less(compare(a, b));
+    SortAlgorithm::default().par_sort(data, true, &lt, pool, threads);
+}
+
+// SECURITY: These compare elements with the type's `Ord` impl, which is only
+// oblivious if that impl compiles to branch-free code. Use them only for
+// comparators that have been checked to be constant time.
+pub fn trusted_sort<T: Ord>(mut list: Vec<T>) -> Vec<T> {
+    bitonic::arbitrary_bitonic_sort(&mut list, true, &|a: &T, b: &T| Condition::from(a < b));
+    list
+}
+
+pub fn par_trusted_sort<T: Ord + Send>(
+    mut list: Vec<T>,
+    pool: &ThreadPool,
+    threads: usize,
+) -> Vec<T> {
+    let lt = |a: &T, b: &T| Condition::from(a < b);
+    bitonic::parallel_arbitrary_bitonic_sort(&mut list, true, &lt, pool, threads);
     list
 }
 
@@ -30,10 +183,20 @@ pub fn par_sort<T: Ord + Send + Max>(
 mod tests {
     use super::*;
 
+    use proptest::prelude::*;
+
     fn is_sorted<T: Ord>(slice: &[T]) -> bool {
         slice.windows(2).all(|w| w[0] <= w[1])
     }
 
+    #[test]
+    fn test_sort() {
+        let a: Vec<u64> = (0..125).rev().chain([u64::MAX, 1 << 63]).collect();
+
+        let a = sort(a);
+        assert!(is_sorted(&a));
+    }
+
     #[test]
     fn test_par_sort() {
         let pool = rayon::ThreadPoolBuilder::new()
@@ -45,4 +208,137 @@ mod tests {
         let a = par_sort(a, &pool, 2);
         assert!(is_sorted(&a));
     }
+
+    #[test]
+    fn test_sort_slice() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        for n in 0..70 {
+            let data: Vec<i32> = (0..n).map(|i| (i * 37 + 11) % 23 - 11).collect();
+            let mut expected = data.clone();
+            expected.sort();
+
+            let mut a = data.clone();
+            sort_slice(&mut a);
+            assert_eq!(a, expected);
+
+            for threads in [2, 3, 8] {
+                let mut a = data.clone();
+                par_sort_slice(&mut a, &pool, threads);
+                assert_eq!(a, expected);
+            }
+
+            for algorithm in [SortAlgorithm::Bitonic, SortAlgorithm::OddEvenMerge] {
+                let mut a = data.clone();
+                sort_slice_with(&mut a, algorithm);
+                assert_eq!(a, expected);
+
+                let mut a = data.clone();
+                par_sort_slice_with(&mut a, algorithm, &pool, 3);
+                assert_eq!(a, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sort_by() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+
+        struct Record {
+            key: u32,
+            group: u8,
+            _payload: [u64; 4],
+        }
+
+        let records = || -> Vec<Record> {
+            (0..100)
+                .map(|i| Record {
+                    key: (i * 37) % 101,
+                    group: (i % 3) as u8,
+                    _payload: [i as u64; 4],
+                })
+                .collect()
+        };
+
+        let mut data = records();
+        sort_by_key(&mut data, |r| r.key);
+        assert!(data.windows(2).all(|w| w[0].key <= w[1].key));
+
+        let mut data = records();
+        par_sort_by_key(&mut data, |r| (r.group, r.key), &pool, 2);
+        assert!(data
+            .windows(2)
+            .all(|w| (w[0].group, w[0].key) <= (w[1].group, w[1].key)));
+
+        // Descending by key.
+        let mut data = records();
+        sort_by(&mut data, |a, b| u32::ocompare(&b.key, &a.key));
+        assert!(data.windows(2).all(|w| w[0].key >= w[1].key));
+
+        let mut data = records();
+        par_sort_by(&mut data, |a, b| u32::ocompare(&b.key, &a.key), &pool, 2);
+        assert!(data.windows(2).all(|w| w[0].key >= w[1].key));
+    }
+
+    // Ordered by key only, so the payload shows the order of equal elements.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Keyed {
+        key: u8,
+        payload: u32,
+    }
+
+    impl ObliviousOps for Keyed {
+        fn oselect(cond: Condition, a: Self, b: Self) -> Self {
+            Keyed {
+                key: u8::oselect(cond, a.key, b.key),
+                payload: u32::oselect(cond, a.payload, b.payload),
+            }
+        }
+
+        fn oequal(a: &Self, b: &Self) -> Condition {
+            u8::oequal(&a.key, &b.key)
+        }
+
+        fn ocompare(a: &Self, b: &Self) -> i8 {
+            u8::ocompare(&a.key, &b.key)
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_stable_sort(data in prop::collection::vec((0u8..4, any::<u32>()), 0..100)) {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(2)
+                .build()
+                .unwrap();
+
+            let data: Vec<Keyed> = data
+                .into_iter()
+                .map(|(key, payload)| Keyed { key, payload })
+                .collect();
+            let mut expected = data.clone();
+            expected.sort_by_key(|k| k.key);
+
+            prop_assert_eq!(stable_sort(data.clone()), expected.clone());
+            prop_assert_eq!(par_stable_sort(data, &pool, 2), expected);
+        }
+    }
+
+    #[test]
+    fn test_trusted_sort() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let a: Vec<i64> = (-62..63).rev().collect();
+
+        assert!(is_sorted(&trusted_sort(a.clone())));
+        assert!(is_sorted(&par_trusted_sort(a, &pool, 2)));
+    }
 }
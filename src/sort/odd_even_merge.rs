@@ -0,0 +1,321 @@
+use crate::ops::{self, Condition};
+use rayon::ThreadPool;
+use std::iter;
+
+// Batcher's odd-even merge sort, in the iterative form that works for any
+// length. Stage (p, k) compares i with i + k whenever both lie in the same block
+// of 2p elements. Comparators that would touch an index past the end are
+// dropped, which is the same as padding with maximal elements that never move.
+// The comparators only depend on n.
+//
+// The comparators of a stage live in disjoint chunks of 2k elements starting at
+// k % p, so the parallel version splits a stage across threads by chunk, and
+// within a chunk by halves as in the bitonic pass.
+//
+// The schedule is also used by the external sort, which runs it over blocks.
+
+// The stages (p, k) of a sort of n elements, in the order they run.
+pub(crate) fn stages(n: usize) -> impl Iterator<Item = (usize, usize)> {
+    iter::successors(Some(1), |p| Some(p * 2))
+        .take_while(move |&p| p < n)
+        .flat_map(|p| iter::successors(Some(p), |&k| (k > 1).then_some(k / 2)).map(move |k| (p, k)))
+}
+
+// Whether stage (p, k) compares i with i + k.
+#[inline]
+pub(crate) fn compares(i: usize, p: usize, k: usize) -> bool {
+    i / (2 * p) == (i + k) / (2 * p)
+}
+
+// The comparators (i, i + k) of stage (p, k) of a sort of n elements.
+#[cfg(any(test, feature = "external"))]
+pub(crate) fn comparators(n: usize, p: usize, k: usize) -> impl Iterator<Item = (usize, usize)> {
+    let start = k % p;
+    (start..n.saturating_sub(k))
+        .filter(move |&i| (i - start) % (2 * k) < k && compares(i, p, k))
+        .map(move |i| (i, i + k))
+}
+
+pub fn parallel_odd_even_merge_sort<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    data: &mut [T],
+    cond: bool,
+    lt: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    if threads <= 1 {
+        odd_even_merge_sort(data, cond, lt);
+        return;
+    }
+
+    let n = data.len();
+    for (p, k) in stages(n) {
+        let start = k % p;
+        if start < n {
+            let chunks = &mut data[start..];
+            parallel_odd_even_stage(chunks, start, p, k, cond, lt, pool, threads);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn parallel_odd_even_stage<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    chunks: &mut [T],
+    offset: usize,
+    p: usize,
+    k: usize,
+    cond: bool,
+    lt: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    if threads <= 1 {
+        odd_even_stage(chunks, offset, p, k, cond, lt);
+        return;
+    }
+
+    let num_chunks = chunks.len().div_ceil(2 * k);
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+
+    if num_chunks > 1 {
+        let mid = (num_chunks / 2) * 2 * k;
+        let (l_chunks, r_chunks) = chunks.split_at_mut(mid);
+        pool.scope(|s| {
+            s.spawn(|_| parallel_odd_even_stage(l_chunks, offset, p, k, cond, lt, pool, l_threads));
+            s.spawn(|_| {
+                parallel_odd_even_stage(r_chunks, offset + mid, p, k, cond, lt, pool, r_threads)
+            });
+        });
+    } else if chunks.len() > k {
+        let (l_half, r_half) = chunks.split_at_mut(k);
+        let len = r_half.len();
+        parallel_odd_even_pass(
+            &mut l_half[..len],
+            r_half,
+            offset,
+            p,
+            k,
+            cond,
+            lt,
+            pool,
+            threads,
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn parallel_odd_even_pass<T: Send, F: Fn(&T, &T) -> Condition + Sync>(
+    l_half: &mut [T],
+    r_half: &mut [T],
+    offset: usize,
+    p: usize,
+    k: usize,
+    cond: bool,
+    lt: &F,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    if threads <= 1 || l_half.len() <= 1 {
+        odd_even_pass(l_half, r_half, offset, p, k, cond, lt);
+        return;
+    }
+
+    let l_threads = threads / 2;
+    let r_threads = threads - l_threads;
+    let mid = l_half.len() / 2;
+
+    let (ll_quarter, lr_quarter) = l_half.split_at_mut(mid);
+    let (rl_quarter, rr_quarter) = r_half.split_at_mut(mid);
+    pool.scope(|s| {
+        s.spawn(|_| {
+            parallel_odd_even_pass(
+                ll_quarter, rl_quarter, offset, p, k, cond, lt, pool, l_threads,
+            )
+        });
+        s.spawn(|_| {
+            let offset = offset + mid;
+            parallel_odd_even_pass(
+                lr_quarter, rr_quarter, offset, p, k, cond, lt, pool, r_threads,
+            )
+        });
+    });
+}
+
+pub fn odd_even_merge_sort<T, F: Fn(&T, &T) -> Condition>(data: &mut [T], cond: bool, lt: &F) {
+    let n = data.len();
+    for (p, k) in stages(n) {
+        let start = k % p;
+        if start < n {
+            odd_even_stage(&mut data[start..], start, p, k, cond, lt);
+        }
+    }
+}
+
+// Runs the comparators of stage (p, k) in chunks, whose first element is at
+// index offset of the whole list.
+fn odd_even_stage<T, F: Fn(&T, &T) -> Condition>(
+    chunks: &mut [T],
+    offset: usize,
+    p: usize,
+    k: usize,
+    cond: bool,
+    lt: &F,
+) {
+    for (c, chunk) in chunks.chunks_mut(2 * k).enumerate() {
+        if chunk.len() > k {
+            let (l_half, r_half) = chunk.split_at_mut(k);
+            let len = r_half.len();
+            odd_even_pass(
+                &mut l_half[..len],
+                r_half,
+                offset + c * 2 * k,
+                p,
+                k,
+                cond,
+                lt,
+            );
+        }
+    }
+}
+
+// Compares l_half[i] with r_half[i] = l_half[i + k], where l_half[0] is at index
+// offset of the whole list, if both are in the same block of 2p elements. The
+// block test only depends on indices.
+#[inline]
+fn odd_even_pass<T, F: Fn(&T, &T) -> Condition>(
+    l_half: &mut [T],
+    r_half: &mut [T],
+    offset: usize,
+    p: usize,
+    k: usize,
+    cond: bool,
+    lt: &F,
+) {
+    let cond = Condition::from(cond);
+    for i in 0..l_half.len() {
+        if compares(offset + i, p, k) {
+            ops::swap(
+                lt(&l_half[i], &r_half[i]) ^ cond,
+                &mut l_half[i],
+                &mut r_half[i],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObliviousOrd;
+    use std::cell::Cell;
+
+    extern crate test;
+    use test::Bencher;
+
+    #[test]
+    fn test_odd_even_merge_sort() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        for n in 0..70 {
+            let data: Vec<i32> = (0..n).map(|i| (i * 37 + 11) % 23 - 11).collect();
+            let mut expected = data.clone();
+            expected.sort();
+
+            let mut a = data.clone();
+            odd_even_merge_sort(&mut a, true, &i32::olt);
+            assert_eq!(a, expected);
+
+            for threads in [2, 3, 8] {
+                let mut a = data.clone();
+                parallel_odd_even_merge_sort(&mut a, true, &i32::olt, &pool, threads);
+                assert_eq!(a, expected);
+            }
+
+            let mut a = data.clone();
+            odd_even_merge_sort(&mut a, false, &i32::olt);
+            expected.reverse();
+            assert_eq!(a, expected);
+        }
+    }
+
+    #[test]
+    fn test_comparators() {
+        for n in 0..70 {
+            let mut data: Vec<i32> = (0..n).map(|i| (i * 37 + 11) % 23 - 11).collect();
+            let mut expected = data.clone();
+            expected.sort();
+
+            for (p, k) in stages(n as usize) {
+                for (i, j) in comparators(n as usize, p, k) {
+                    if data[j] < data[i] {
+                        data.swap(i, j);
+                    }
+                }
+            }
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[test]
+    fn test_fewer_comparisons() {
+        let count = Cell::new(0);
+        let lt = |a: &u64, b: &u64| {
+            count.set(count.get() + 1);
+            u64::olt(a, b)
+        };
+
+        let mut v: Vec<u64> = (0..1024).rev().collect();
+        odd_even_merge_sort(&mut v, true, &lt);
+        let odd_even = count.replace(0);
+
+        let mut v: Vec<u64> = (0..1024).rev().collect();
+        crate::sort::bitonic::bitonic_sort(&mut v, true, &lt);
+        let bitonic = count.get();
+
+        assert!(odd_even < bitonic);
+    }
+
+    #[bench]
+    fn bench_odd_even_merge_sort(b: &mut Bencher) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+
+        let size = 0x100000;
+        let mut v: Vec<i64> = (0..size).rev().collect();
+
+        b.iter(|| parallel_odd_even_merge_sort(&mut v[..], true, &i64::olt, &pool, 8));
+    }
+
+    struct BigElem {
+        key: u64,
+        _dum: [u64; 15],
+    }
+
+    impl BigElem {
+        fn new(id: u64) -> Self {
+            BigElem {
+                key: id,
+                _dum: [0; 15],
+            }
+        }
+    }
+
+    #[bench]
+    fn bench_big_odd_even_merge_sort(b: &mut Bencher) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+        let size = 0x100000;
+        let mut v: Vec<BigElem> = (0..size).rev().map(BigElem::new).collect();
+        let lt = |a: &BigElem, b: &BigElem| u64::olt(&a.key, &b.key);
+
+        b.iter(|| parallel_odd_even_merge_sort(&mut v[..], true, &lt, &pool, 8));
+    }
+}
@@ -0,0 +1,128 @@
+use super::SortAlgorithm;
+use crate::ops::{is_less, then};
+use crate::ObliviousOps;
+use rayon::ThreadPool;
+
+// A lexicographic sort order over keys of T, each ascending or descending:
+//
+//     SortOrder::new().desc(|r: &Row| r.a).asc(|r: &Row| r.b).sort(rows)
+//
+// Every key is compared with ocompare for every comparison, whichever key
+// decides it. With no keys, all elements compare equal.
+type Compare<'a, T> = Box<dyn Fn(&T, &T) -> i8 + Sync + 'a>;
+
+pub struct SortOrder<'a, T> {
+    keys: Vec<Compare<'a, T>>,
+}
+
+impl<T> Default for SortOrder<'_, T> {
+    fn default() -> Self {
+        SortOrder { keys: Vec::new() }
+    }
+}
+
+impl<'a, T> SortOrder<'a, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn asc<K: ObliviousOps, F: Fn(&T) -> K + Sync + 'a>(mut self, key: F) -> Self {
+        self.keys
+            .push(Box::new(move |a, b| K::ocompare(&key(a), &key(b))));
+        self
+    }
+
+    pub fn desc<K: ObliviousOps, F: Fn(&T) -> K + Sync + 'a>(mut self, key: F) -> Self {
+        self.keys
+            .push(Box::new(move |a, b| K::ocompare(&key(b), &key(a))));
+        self
+    }
+
+    // Compares like ocompare: -1, 0 or 1.
+    pub fn compare(&self, a: &T, b: &T) -> i8 {
+        self.keys
+            .iter()
+            .fold(0, |prev, compare| then(prev, compare(a, b)))
+    }
+
+    pub fn sort(&self, mut list: Vec<T>) -> Vec<T> {
+        self.sort_slice(&mut list);
+        list
+    }
+
+    pub fn par_sort(&self, mut list: Vec<T>, pool: &ThreadPool, threads: usize) -> Vec<T>
+    where
+        T: Send,
+    {
+        self.par_sort_slice(&mut list, pool, threads);
+        list
+    }
+
+    pub fn sort_slice(&self, data: &mut [T]) {
+        let lt = |a: &T, b: &T| is_less(self.compare(a, b));
+        SortAlgorithm::default().sort(data, true, &lt);
+    }
+
+    pub fn par_sort_slice(&self, data: &mut [T], pool: &ThreadPool, threads: usize)
+    where
+        T: Send,
+    {
+        let lt = |a: &T, b: &T| is_less(self.compare(a, b));
+        SortAlgorithm::default().par_sort(data, true, &lt, pool, threads);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Row {
+        a: u8,
+        b: i32,
+        c: u64,
+    }
+
+    fn rows() -> Vec<Row> {
+        (0..200)
+            .map(|i| Row {
+                a: (i % 5) as u8,
+                b: (i * 37) % 11 - 5,
+                c: i as u64,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_order() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+
+        let order = SortOrder::new()
+            .desc(|r: &Row| r.a)
+            .asc(|r: &Row| r.b)
+            .desc(|r: &Row| r.c);
+        let mut expected = rows();
+        expected.sort_by(|x, y| (y.a, x.b, y.c).cmp(&(x.a, y.b, x.c)));
+
+        assert_eq!(order.sort(rows()), expected);
+        assert_eq!(order.par_sort(rows(), &pool, 2), expected);
+
+        let mut data = rows();
+        order.sort_slice(&mut data);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_compare() {
+        let order = SortOrder::new().asc(|r: &Row| r.a).desc(|r: &Row| r.b);
+        let (x, y) = (Row { a: 1, b: 2, c: 0 }, Row { a: 1, b: 3, c: 9 });
+
+        assert_eq!(order.compare(&x, &y), 1);
+        assert_eq!(order.compare(&y, &x), -1);
+        assert_eq!(order.compare(&x, &x), 0);
+        assert_eq!(SortOrder::new().compare(&x, &y), 0);
+    }
+}